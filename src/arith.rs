@@ -0,0 +1,514 @@
+use std::env;
+
+use crate::shell;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(i64),
+    Name(String),
+    Op(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    Unary(String, Box<Expr>),
+    Binary(String, Box<Expr>, Box<Expr>),
+    /// `a ? b : c`
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// e.g. `i += 2` would be: `Assign("i", "+", 2)`
+    Assign(String, String, Box<Expr>),
+    /// `++i` / `--i`
+    PreIncr(String, i64),
+    /// `i++` / `i--`
+    PostIncr(String, i64),
+}
+
+// longer ones first
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "==", "!=",
+    "<=", ">=", "&&", "||", "<<", ">>", "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "|",
+    "^", "?", ":", ",", "(", ")",
+];
+
+/// Parse integer literals like `42`, `0x2a`, `052` (octal) and `2#101010`.
+fn parse_number(text: &str) -> Result<i64, String> {
+    let too_great = || format!("{}: value too great for base", text);
+    let (base, digits) = if let Some(pos) = text.find('#') {
+        match text[..pos].parse::<u32>() {
+            Ok(x) if (2..=64).contains(&x) => (x, &text[pos + 1..]),
+            _ => return Err(format!("{}: invalid arithmetic base", text)),
+        }
+    } else if text.starts_with("0x") || text.starts_with("0X") {
+        (16, &text[2..])
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    if digits.is_empty() {
+        return Err(too_great());
+    }
+
+    let mut result: i64 = 0;
+    for c in digits.chars() {
+        // digits of bases larger than 36 are: 0-9, a-z, A-Z, `@` and `_`
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(too_great()),
+        };
+        if digit >= base {
+            return Err(too_great());
+        }
+        result = result.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(result)
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    'outer: while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let mut s = String::new();
+            while i < chars.len() {
+                let x = chars[i];
+                let is_digit = x.is_ascii_alphanumeric()
+                    || (x == '#' && !s.contains('#'))
+                    || ((x == '@' || x == '_') && s.contains('#'));
+                if !is_digit {
+                    break;
+                }
+                s.push(x);
+                i += 1;
+            }
+            tokens.push(Token::Num(parse_number(&s)?));
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let mut s = String::new();
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                s.push(chars[i]);
+                i += 1;
+            }
+            tokens.push(Token::Name(s));
+            continue;
+        }
+        for op in OPERATORS.iter() {
+            let len = op.chars().count();
+            if i + len <= chars.len() && chars[i..i + len].iter().collect::<String>() == *op {
+                tokens.push(Token::Op(op.to_string()));
+                i += len;
+                continue 'outer;
+            }
+        }
+        return Err(format!(
+            "syntax error: invalid arithmetic operator (error token is \"{}\")",
+            c
+        ));
+    }
+    Ok(tokens)
+}
+
+/// Binary operators and their precedences, higher binds tighter.
+fn binary_precedence(op: &str) -> Option<i32> {
+    let prec = match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | ">" | "<=" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        "**" => 11,
+        _ => return None,
+    };
+    Some(prec)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_op(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Op(x)) => Some(x.as_str()),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Parse expressions like `a = 1, b = 2`, the value is the last one.
+    fn parse_comma(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_assign()?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            let right = self.parse_assign()?;
+            expr = Expr::Binary(String::from(","), Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_assign(&mut self) -> Result<Expr, String> {
+        if let Some(Token::Name(name)) = self.peek() {
+            let name = name.clone();
+            if let Some(Token::Op(op)) = self.tokens.get(self.pos + 1) {
+                let op = op.clone();
+                let is_assign = op == "="
+                    || (op.ends_with('=')
+                        && binary_precedence(&op[..op.len() - 1]).is_some()
+                        && !["<", ">", "=", "!"].contains(&&op[..op.len() - 1]));
+                if is_assign {
+                    self.pos += 2;
+                    let value = self.parse_assign()?;
+                    let op = op[..op.len() - 1].to_string();
+                    return Ok(Expr::Assign(name, op, Box::new(value)));
+                }
+            }
+        }
+        self.parse_cond()
+    }
+
+    fn parse_cond(&mut self) -> Result<Expr, String> {
+        let cond = self.parse_binary(1)?;
+        if self.peek_op() != Some("?") {
+            return Ok(cond);
+        }
+        self.pos += 1;
+        let left = self.parse_assign()?;
+        if self.next() != Some(Token::Op(String::from(":"))) {
+            return Err(String::from(
+                "syntax error: `:' expected for conditional expression",
+            ));
+        }
+        let right = self.parse_assign()?;
+        Ok(Expr::Cond(Box::new(cond), Box::new(left), Box::new(right)))
+    }
+
+    fn parse_binary(&mut self, min_prec: i32) -> Result<Expr, String> {
+        let mut left = self.parse_unary()?;
+        while let Some(op) = self.peek_op() {
+            let op = op.to_string();
+            let prec = match binary_precedence(&op) {
+                Some(x) => x,
+                None => break,
+            };
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            // `**` is right-associative: `2 ** 3 ** 2` is `2 ** 9`
+            let next_prec = if op == "**" { prec } else { prec + 1 };
+            let right = self.parse_binary(next_prec)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        let op = self.peek_op().unwrap_or("").to_string();
+        if op == "++" || op == "--" {
+            self.pos += 1;
+            let delta = if op == "++" { 1 } else { -1 };
+            match self.next() {
+                Some(Token::Name(name)) => return Ok(Expr::PreIncr(name, delta)),
+                _ => return Err(format!("syntax error: operand expected after `{}'", op)),
+            }
+        }
+        if op == "-" || op == "+" || op == "!" || op == "~" {
+            self.pos += 1;
+            let operand = self.parse_unary()?;
+            return Ok(Expr::Unary(op, Box::new(operand)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Name(name)) => {
+                let op = self.peek_op().unwrap_or("").to_string();
+                if op == "++" || op == "--" {
+                    self.pos += 1;
+                    let delta = if op == "++" { 1 } else { -1 };
+                    return Ok(Expr::PostIncr(name, delta));
+                }
+                Ok(Expr::Var(name))
+            }
+            Some(Token::Op(ref x)) if x == "(" => {
+                let expr = self.parse_comma()?;
+                if self.next() != Some(Token::Op(String::from(")"))) {
+                    return Err(String::from("syntax error: `)' expected"));
+                }
+                Ok(expr)
+            }
+            Some(Token::Op(x)) => Err(format!(
+                "syntax error: operand expected (error token is \"{}\")",
+                x
+            )),
+            None => Err(String::from("syntax error: operand expected")),
+        }
+    }
+}
+
+fn get_var(sh: &shell::Shell, name: &str) -> Result<i64, String> {
+    let value = if let Some(x) = sh.get_local_env(name) {
+        x
+    } else if let Some(x) = sh.get_array(name) {
+        // `arr` is the same as `arr[0]`
+        x.get("0").cloned().unwrap_or_default()
+    } else if let Ok(x) = env::var(name) {
+        x
+    } else if let Some(x) = sh.get_env(name) {
+        x
+    } else {
+        return Ok(0);
+    };
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(x) => (true, x),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    match parse_number(digits) {
+        Ok(x) if negative => Ok(x.wrapping_neg()),
+        Ok(x) => Ok(x),
+        Err(_) => Err(format!("{}: invalid number: {}", name, value)),
+    }
+}
+
+fn calc(op: &str, left: i64, right: i64) -> Result<i64, String> {
+    let result = match op {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" => {
+            if right == 0 {
+                return Err(String::from("division by 0"));
+            }
+            if op == "/" {
+                left.wrapping_div(right)
+            } else {
+                left.wrapping_rem(right)
+            }
+        }
+        "**" => {
+            if right < 0 {
+                return Err(String::from("exponent less than 0"));
+            }
+            left.wrapping_pow(right.min(u32::MAX as i64) as u32)
+        }
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "&" => left & right,
+        "|" => left | right,
+        "^" => left ^ right,
+        "," => right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        ">" => (left > right) as i64,
+        "<=" => (left <= right) as i64,
+        ">=" => (left >= right) as i64,
+        _ => return Err(format!("unknown operator: {}", op)),
+    };
+    Ok(result)
+}
+
+fn eval_expr(sh: &mut shell::Shell, expr: &Expr) -> Result<i64, String> {
+    match expr {
+        Expr::Num(n) => Ok(*n),
+        Expr::Var(name) => get_var(sh, name),
+        Expr::Unary(op, operand) => {
+            let value = eval_expr(sh, operand)?;
+            match op.as_str() {
+                "-" => Ok(value.wrapping_neg()),
+                "!" => Ok((value == 0) as i64),
+                "~" => Ok(!value),
+                _ => Ok(value),
+            }
+        }
+        Expr::Binary(op, left, right) => {
+            let left = eval_expr(sh, left)?;
+            // `&&` and `||` do short-circuit evaluation
+            if op == "&&" {
+                if left == 0 {
+                    return Ok(0);
+                }
+                return Ok((eval_expr(sh, right)? != 0) as i64);
+            }
+            if op == "||" {
+                if left != 0 {
+                    return Ok(1);
+                }
+                return Ok((eval_expr(sh, right)? != 0) as i64);
+            }
+            let right = eval_expr(sh, right)?;
+            calc(op, left, right)
+        }
+        Expr::Cond(cond, left, right) => {
+            if eval_expr(sh, cond)? != 0 {
+                eval_expr(sh, left)
+            } else {
+                eval_expr(sh, right)
+            }
+        }
+        Expr::Assign(name, op, value) => {
+            let mut value = eval_expr(sh, value)?;
+            if !op.is_empty() {
+                value = calc(op, get_var(sh, name)?, value)?;
+            }
+            sh.set_env(name, &value.to_string());
+            Ok(value)
+        }
+        Expr::PreIncr(name, delta) => {
+            let value = get_var(sh, name)? + delta;
+            sh.set_env(name, &value.to_string());
+            Ok(value)
+        }
+        Expr::PostIncr(name, delta) => {
+            let value = get_var(sh, name)?;
+            sh.set_env(name, &(value + delta).to_string());
+            Ok(value)
+        }
+    }
+}
+
+/// Evaluate shell arithmetic expressions like `i < 10` or `i += 2`.
+/// Variables are read from (and assigned to) the shell.
+pub fn eval(sh: &mut shell::Shell, expr: &str) -> Result<i64, String> {
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let result = parser.parse_comma()?;
+    if let Some(token) = parser.peek() {
+        let token = match token {
+            Token::Num(n) => n.to_string(),
+            Token::Name(x) | Token::Op(x) => x.clone(),
+        };
+        return Err(format!(
+            "syntax error in expression (error token is \"{}\")",
+            token
+        ));
+    }
+    eval_expr(sh, &result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::eval;
+    use crate::scripting::{check, run_lines};
+    use crate::shell;
+
+    #[test]
+    fn test_eval() {
+        let mut sh = shell::Shell::new();
+        let v = vec![
+            ("1 + 2 * 3", 7),
+            ("(1 + 2) * 3", 9),
+            ("7 / 2", 3),
+            ("7 % 3", 1),
+            ("-3 + 1", -2),
+            ("1 < 2 && 2 <= 2", 1),
+            ("1 > 2 || 0", 0),
+            ("!0", 1),
+            ("3 == 3", 1),
+            ("3 != 3", 0),
+            ("10 - 2 - 3", 5),
+            ("2 ** 3 ** 2", 512),
+            ("-2 ** 2", 4),
+            ("1 << 4 | 1", 17),
+            ("6 & 3 ^ 1", 3),
+            ("~5 >> 1", -3),
+            ("0x1F + 010 + 2#101 + 64#_", 107),
+            ("1 ? 2 : 3", 2),
+            ("0 ? 2 : 1 ? 3 : 4", 3),
+            ("(1, 2) + 1", 3),
+            ("", 0),
+        ];
+        for (expr, expected) in v {
+            assert_eq!(eval(&mut sh, expr), Ok(expected));
+        }
+        assert!(eval(&mut sh, "1 / 0").is_err());
+        assert!(eval(&mut sh, "1 +").is_err());
+        assert!(eval(&mut sh, "(1 + 2").is_err());
+        assert!(eval(&mut sh, "1 2").is_err());
+        assert!(eval(&mut sh, "09").is_err());
+        assert!(eval(&mut sh, "1#1").is_err());
+        assert!(eval(&mut sh, "2 ** -1").is_err());
+        assert!(eval(&mut sh, "1 ? 2").is_err());
+    }
+
+    #[test]
+    fn test_eval_vars() {
+        let mut sh = shell::Shell::new();
+        assert_eq!(eval(&mut sh, "i = 3"), Ok(3));
+        assert_eq!(sh.get_env("i"), Some("3".to_string()));
+        assert_eq!(eval(&mut sh, "i += 2"), Ok(5));
+        assert_eq!(eval(&mut sh, "i++"), Ok(5));
+        assert_eq!(eval(&mut sh, "i"), Ok(6));
+        assert_eq!(eval(&mut sh, "--i"), Ok(5));
+        assert_eq!(eval(&mut sh, "i *= i < 10"), Ok(5));
+        assert_eq!(eval(&mut sh, "j = i = 2"), Ok(2));
+        assert_eq!(sh.get_env("j"), Some("2".to_string()));
+        assert_eq!(eval(&mut sh, "not_defined + 1"), Ok(1));
+        assert_eq!(eval(&mut sh, "0 && (k = 1)"), Ok(0));
+        assert_eq!(sh.get_env("k"), None);
+        assert_eq!(eval(&mut sh, "i = 1, i <<= 3, i + i"), Ok(16));
+        assert_eq!(eval(&mut sh, "i |= 3"), Ok(11));
+        assert_eq!(eval(&mut sh, "i > 5 ? (k = 1) : (k = 2)"), Ok(1));
+        assert_eq!(sh.get_env("k"), Some("1".to_string()));
+        sh.set_env("h", "0x10");
+        assert_eq!(eval(&mut sh, "h + 1"), Ok(17));
+        sh.set_env("n", "-3");
+        assert_eq!(eval(&mut sh, "n * 2"), Ok(-6));
+    }
+
+    #[test]
+    fn test_run_arith() {
+        let mut sh = shell::Shell::new();
+        let v = [
+            ("i=3; (( i++ )); S=$i", "4"),
+            ("n=2; S=$(( n * (n + 1) ))", "6"),
+            ("(( 1 > 2 )) && S=yes || S=no", "no"),
+            ("(( x = 5, x % 2 )) && S=odd$x", "odd5"),
+            ("n=3; while (( n-- )); do S=$S$n; done", "210"),
+            ("n=2; for ((i = n; i < $n + 2; i++)); do S=$S$i; done", "23"),
+        ];
+        check(&mut sh, &v);
+
+        assert_eq!(run_lines(&mut sh, "(( 0 ))"), 1);
+        assert_eq!(run_lines(&mut sh, "(( 1 / 0 ))"), 1);
+        assert_eq!(run_lines(&mut sh, "echo $(( 1 / 0 ))"), 1);
+    }
+}
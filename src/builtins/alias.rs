@@ -0,0 +1,47 @@
+use std::io::Write;
+
+use crate::parsers;
+use crate::shell;
+use crate::tools;
+use crate::types::Tokens;
+
+/// `alias ll='ls -lh'` defines an alias, `alias ll` prints it, and
+/// `alias` prints all of them.
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    if tokens.len() == 1 {
+        let mut names: Vec<&String> = sh.alias.keys().collect();
+        names.sort();
+        for name in names {
+            print_alias(name, &sh.alias[name]);
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for (_, text) in tokens.iter().skip(1) {
+        match text.find('=') {
+            Some(i) => {
+                let name = &text[..i];
+                if !tools::re_contains(name, r"^[a-zA-Z0-9_\.-]+$") {
+                    println_stderr!("cicada: alias: {}: invalid alias name", name);
+                    status = 1;
+                    continue;
+                }
+                let value = parsers::parser_line::unquote(&text[i + 1..]);
+                sh.add_alias(name, &value);
+            }
+            None => match sh.get_alias_content(text) {
+                Some(x) => print_alias(text, &x),
+                None => {
+                    println_stderr!("cicada: alias: {}: not found", text);
+                    status = 1;
+                }
+            },
+        }
+    }
+    status
+}
+
+fn print_alias(name: &str, value: &str) {
+    println!("alias {}='{}'", name, value.replace('\'', "'\\''"));
+}
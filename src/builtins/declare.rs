@@ -0,0 +1,92 @@
+use std::io::Write;
+
+use crate::shell;
+use crate::tools;
+use crate::types::{self, Tokens};
+
+fn print_arrays(sh: &shell::Shell) {
+    let mut names: Vec<&String> = sh.arrays.keys().collect();
+    names.sort();
+    for name in names {
+        let array = &sh.arrays[name];
+        let flag = match array {
+            types::Array::Indexed(_) => "-a",
+            types::Array::Assoc(_) => "-A",
+        };
+        let items: Vec<String> = array
+            .keys()
+            .iter()
+            .zip(array.values().iter())
+            .map(|(k, v)| format!("[{}]={:?}", k, v))
+            .collect();
+        println!("declare {} {}=({})", flag, name, items.join(" "));
+    }
+}
+
+/// `declare -a arr`, `declare -A map=([k]=v)` and `declare foo=bar`,
+/// declare (array) variables. Without names, it prints the arrays.
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    let mut assoc = false;
+    let mut indexed = false;
+    let mut has_names = false;
+    let mut status = 0;
+    for (_, text) in tokens.iter().skip(1) {
+        if text.starts_with('-') {
+            for c in text.chars().skip(1) {
+                match c {
+                    'a' => indexed = true,
+                    'A' => assoc = true,
+                    _ => {
+                        println_stderr!("cicada: declare: -{}: invalid option", c);
+                        println_stderr!("usage: declare [-a|-A] name[=value] ...");
+                        return 2;
+                    }
+                }
+            }
+            continue;
+        }
+
+        has_names = true;
+        let name = text.split(&['=', '+'][..]).next().unwrap_or("");
+        if !tools::re_contains(name, r"^[a-zA-Z_][a-zA-Z0-9_]*$") {
+            println_stderr!("cicada: declare: `{}': not a valid identifier", text);
+            status = 1;
+            continue;
+        }
+        match sh.get_array(name) {
+            Some(types::Array::Indexed(_)) if assoc => {
+                println_stderr!(
+                    "cicada: declare: {}: cannot convert indexed to associative array",
+                    name
+                );
+                status = 1;
+                continue;
+            }
+            Some(_) => {}
+            None if assoc => {
+                let array = types::Array::Assoc(Default::default());
+                sh.set_array(name, array);
+            }
+            None if indexed => {
+                let mut array = types::Array::Indexed(Default::default());
+                if let Some(x) = sh.get_env(name) {
+                    array.push(&x);
+                }
+                sh.set_array(name, array);
+            }
+            None => {}
+        }
+
+        if text.len() > name.len() {
+            if let Err(e) = shell::run_assignment(sh, text) {
+                println_stderr!("cicada: declare: {}", e);
+                status = 1;
+            }
+        }
+    }
+
+    if !has_names {
+        print_arrays(sh);
+    }
+    status
+}
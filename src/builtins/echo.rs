@@ -0,0 +1,66 @@
+use crate::builtins::printf;
+use crate::types::Tokens;
+
+/// `echo [-neE] [arg ...]` prints the arguments separated by spaces. `-n`
+/// omits the trailing newline, and `-e` expands the backslash escapes like
+/// `\t` and `\n` (`-E` turns it off again).
+pub fn run(tokens: &Tokens) -> i32 {
+    let mut newline = true;
+    let mut escapes = false;
+    let mut i = 1;
+    while i < tokens.len() {
+        // things like `-x` or `-nx` are printed as they are
+        let arg = &tokens[i].1;
+        if arg.len() < 2 || !arg.starts_with('-') || !arg[1..].chars().all(|c| "neE".contains(c)) {
+            break;
+        }
+        for c in arg[1..].chars() {
+            match c {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        i += 1;
+    }
+
+    let mut output = Vec::new();
+    for (j, token) in tokens[i..].iter().enumerate() {
+        if j > 0 {
+            output.push(b' ');
+        }
+        if !escapes {
+            output.extend(token.1.as_bytes());
+            continue;
+        }
+        let (bytes, stop) = printf::expand_escapes(&token.1);
+        output.extend(bytes);
+        if stop {
+            newline = false;
+            break;
+        }
+    }
+    if newline {
+        output.push(b'\n');
+    }
+    printf::write_output("echo", &output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scripting::check;
+    use crate::shell;
+
+    #[test]
+    fn test_run_echo() {
+        let mut sh = shell::Shell::new();
+        let v = [
+            (
+                "S=$(echo a  b; echo -n c; echo -e 'd\\te\\c'; echo f)",
+                "a b\ncd\tef",
+            ),
+            ("S=$(echo -nx; echo -E 'a\\n' -n)", "-nx\na\\n -n"),
+        ];
+        check(&mut sh, &v);
+    }
+}
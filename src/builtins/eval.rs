@@ -0,0 +1,36 @@
+use crate::execute;
+use crate::shell;
+use crate::types::Tokens;
+
+/// `eval "$(ssh-agent -s)"` joins the args with spaces, and runs the
+/// result as commands in current shell, so that changes like `cd`,
+/// variables and aliases made by them are kept.
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens, tty: bool) -> i32 {
+    let args: Vec<&str> = tokens[1..].iter().map(|x| x.1.as_str()).collect();
+    let line = args.join(" ");
+    if line.trim().is_empty() {
+        return 0;
+    }
+    execute::run_procs(sh, &line, tty)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scripting::{check, run_lines};
+    use crate::shell;
+
+    #[test]
+    fn test_run_eval() {
+        let mut sh = shell::Shell::new();
+        let v = [
+            ("eval 'S=a; alias foo=bar'", "a"),
+            ("cmd='S=b'; eval $cmd", "b"),
+            ("eval \"$(echo 'S=c; T=d;')\"; S=$S$T", "cd"),
+            ("eval 'for i in 1 2; do' 'S=$S$i;' done", "12"),
+            ("eval; S=$?", "0"),
+        ];
+        check(&mut sh, &v);
+        assert_eq!(sh.get_alias_content("foo"), Some("bar".to_string()));
+        assert_eq!(run_lines(&mut sh, "eval false"), 1);
+    }
+}
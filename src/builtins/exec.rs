@@ -1,17 +1,48 @@
+use std::io::Write;
+
+use libc;
+
+use crate::execute;
 use crate::parsers;
+use crate::shell;
 use crate::types::Tokens;
 use exec;
 
-pub fn run(tokens: &Tokens) -> i32 {
-    let args = parsers::parser_line::tokens_to_args(&tokens);
+pub fn run(sh: &shell::Shell, tokens: &Tokens) -> i32 {
+    let cmd = match parsers::parser_line::cmd_to_with_redirects(&tokens) {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: exec: {}", e);
+            return 1;
+        }
+    };
+    let saved = match execute::apply_redirects(&cmd.redirects, sh.options.noclobber) {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: exec: {}", e);
+            return 1;
+        }
+    };
+
+    let args = parsers::parser_line::tokens_to_args(&cmd.tokens);
     let len = args.len();
     if len == 1 {
-        println!("invalid command");
-        return 1;
+        if cmd.redirects.is_empty() {
+            println!("invalid command");
+            return 1;
+        }
+        // e.g. `exec 3> foo.log`, the redirections stay for the shell
+        for (_, fd_saved) in saved {
+            unsafe {
+                libc::close(fd_saved);
+            }
+        }
+        return 0;
     }
 
     let mut cmd = exec::Command::new(&args[1]);
     let err = cmd.args(&args[2..len]).exec();
+    execute::restore_fds(saved);
     println!("exec error: {:?}", err);
     0
 }
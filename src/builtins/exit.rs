@@ -2,10 +2,11 @@
 use std::io::Write;
 use std::process;
 
+use crate::builtins;
 use crate::shell;
 use crate::types::Tokens;
 
-pub fn run(sh: &shell::Shell, tokens: &Tokens) -> i32 {
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
     if tokens.len() > 2 {
         println_stderr!("cicada: exit: too many arguments");
         return 1;
@@ -15,10 +16,12 @@ pub fn run(sh: &shell::Shell, tokens: &Tokens) -> i32 {
         let _code = &tokens[1].1;
         match _code.parse::<i32>() {
             Ok(x) => {
+                builtins::trap::run_exit(sh);
                 process::exit(x);
             }
             Err(_) => {
                 println_stderr!("cicada: exit: {}: numeric argument required", _code);
+                builtins::trap::run_exit(sh);
                 process::exit(255);
             }
         }
@@ -32,5 +35,6 @@ pub fn run(sh: &shell::Shell, tokens: &Tokens) -> i32 {
         }
     }
 
+    builtins::trap::run_exit(sh);
     process::exit(0);
 }
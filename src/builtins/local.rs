@@ -0,0 +1,134 @@
+use regex::Regex;
+use std::io::Write;
+
+use crate::parsers;
+use crate::shell;
+use crate::types::{self, Tokens};
+
+/// `local foo bar=1 arr=(a b)`, declare variables only visible in current
+/// function (and functions called by it).
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    if sh.scopes.is_empty() {
+        println_stderr!("cicada: local: can only be used in a function");
+        return 1;
+    }
+
+    let re = match Regex::new(r"^([a-zA-Z_][a-zA-Z0-9_]*)(=(.*))?$") {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: Regex error: {:?}", e);
+            return 1;
+        }
+    };
+
+    if tokens.len() == 1 {
+        if let Some(scope) = sh.scopes.last() {
+            let mut names: Vec<&String> = scope.envs.keys().collect();
+            names.sort();
+            for name in names {
+                println!("{}={}", name, scope.envs[name]);
+            }
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for (_, text) in tokens.iter().skip(1) {
+        match re.captures(text) {
+            Some(cap) => {
+                // arrays like `local arr=(a b)`
+                if cap.get(3).is_some_and(|x| x.as_str().starts_with('(')) {
+                    let array = types::Array::Indexed(Default::default());
+                    sh.set_local_array(&cap[1], array);
+                    if let Err(e) = shell::run_assignment(sh, text) {
+                        println_stderr!("cicada: local: {}", e);
+                        status = 1;
+                    }
+                    continue;
+                }
+                let value = match cap.get(3) {
+                    Some(x) => parsers::parser_line::unquote(x.as_str()),
+                    None => String::new(),
+                };
+                sh.set_local_env(&cap[1], &value);
+            }
+            None => {
+                println_stderr!("cicada: local: `{}': not a valid identifier", text);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::execute;
+    use crate::scripting::{check, run_lines};
+    use crate::shell;
+
+    #[test]
+    fn test_run_local() {
+        let mut sh = shell::Shell::new();
+        let text = "
+inner() {
+    S=\"$S inner:$x,$y\"
+    x=inner
+    y=inner
+}
+outer() {
+    local x=outer y
+    S=\"$S outer:$x,$y\"
+    inner
+    S=\"$S outer:$x,$y\"
+    local i
+    for i in 1 2; do true; done
+}
+x=global
+y=global
+i=0
+outer
+";
+        run_lines(&mut sh, text);
+        assert_eq!(
+            sh.get_env("S").unwrap().trim(),
+            "outer:outer, inner:outer, outer:inner,inner"
+        );
+        assert_eq!(sh.get_env("x"), Some("global".to_string()));
+        assert_eq!(sh.get_env("y"), Some("global".to_string()));
+        assert_eq!(sh.get_env("i"), Some("0".to_string()));
+        assert!(sh.scopes.is_empty());
+
+        assert_eq!(execute::run_procs(&mut sh, "local x=1", false), 1);
+        assert_eq!(sh.get_env("x"), Some("global".to_string()));
+        assert_eq!(
+            execute::run_procs(&mut sh, "f() { local 1x; }; f", false),
+            1
+        );
+
+        // locals shadow the arrays, and could be arrays too
+        let v = [
+            (
+                "a=(1 2); f() { local a=(9); a[1]=8; S=${a[@]}; }; f; S=\"$S ${a[@]}\"",
+                "9 8 1 2",
+            ),
+            (
+                "a=(1 2); f() { local a=x; S=$a; }; f; S=\"$S ${a[@]}\"",
+                "x 1 2",
+            ),
+            (
+                "a=x; f() { local a=(1 2); g; }; g() { S=${a[1]}; }; f; S=$S$a",
+                "2x",
+            ),
+        ];
+        check(&mut sh, &v);
+
+        // locals shadow the exported variables in child processes too
+        let text = "export CICADA_LOCAL=1
+f() { local CICADA_LOCAL=2; S=$(printenv CICADA_LOCAL); }
+f
+S=\"$S $(printenv CICADA_LOCAL)\"";
+        run_lines(&mut sh, text);
+        assert_eq!(sh.get_env("S"), Some("2 1".to_string()));
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::Write;
+
+use crate::shell;
+use crate::types::Tokens;
+
+/// Parse the `N` of `break N` / `continue N`, clamped to the loop depth.
+fn get_loop_num(sh: &shell::Shell, name: &str, tokens: &Tokens) -> Option<i32> {
+    if sh.loop_depth == 0 {
+        println_stderr!(
+            "cicada: {}: only meaningful in a `for', `while', or `until' loop",
+            name
+        );
+        return None;
+    }
+    if tokens.len() > 2 {
+        println_stderr!("cicada: {}: too many arguments", name);
+        return None;
+    }
+
+    let mut num = 1;
+    if tokens.len() == 2 {
+        match tokens[1].1.parse::<i32>() {
+            Ok(x) => num = x,
+            Err(_) => {
+                println_stderr!(
+                    "cicada: {}: {}: numeric argument required",
+                    name,
+                    tokens[1].1
+                );
+                return None;
+            }
+        }
+        if num < 1 {
+            println_stderr!("cicada: {}: {}: loop count out of range", name, num);
+            return None;
+        }
+    }
+    if num > sh.loop_depth {
+        num = sh.loop_depth;
+    }
+    Some(num)
+}
+
+pub fn run_break(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    match get_loop_num(sh, "break", tokens) {
+        Some(num) => {
+            sh.break_num = num;
+            0
+        }
+        None => 1,
+    }
+}
+
+pub fn run_continue(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    match get_loop_num(sh, "continue", tokens) {
+        Some(num) => {
+            sh.continue_num = num;
+            0
+        }
+        None => 1,
+    }
+}
@@ -1,10 +1,24 @@
+pub mod alias;
 pub mod bg;
 pub mod cd;
 pub mod cinfo;
+pub mod declare;
+pub mod echo;
+pub mod eval;
 pub mod exec;
 pub mod exit;
 pub mod export;
 pub mod fg;
 pub mod history;
 pub mod jobs;
+pub mod local;
+pub mod loopctl;
+pub mod printf;
+pub mod read;
+pub mod ret;
+pub mod set;
+pub mod shift;
+pub mod source;
+pub mod test;
+pub mod trap;
 pub mod vox;
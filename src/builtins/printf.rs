@@ -0,0 +1,619 @@
+use std::io::{self, Write};
+
+use crate::shell;
+use crate::tools;
+use crate::types::Tokens;
+
+/// A conversion of the format like `%-8s`, `%05.2f` or `%#x`.
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    conv: char,
+}
+
+/// The largest width and precision, so that a huge one like `%5000000000d`
+/// could not make us allocate gigabytes of padding.
+const MAX_WIDTH: u64 = 1 << 20;
+
+struct Printer {
+    args: Vec<String>,
+    pos: usize,
+    output: Vec<u8>,
+    status: i32,
+}
+
+/// `printf [-v var] FORMAT [arg ...]` prints the arguments by the format,
+/// which is reused until all arguments are consumed. With `-v`, the output
+/// is assigned to the variable instead.
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    let mut args: Vec<String> = tokens[1..].iter().map(|x| x.1.clone()).collect();
+    let mut var = None;
+    if args.first().map(String::as_str) == Some("-v") {
+        if args.len() < 2 {
+            println_stderr!("cicada: printf: -v: option requires an argument");
+            return 2;
+        }
+        if !tools::re_contains(&args[1], r"^[a-zA-Z_][a-zA-Z0-9_]*$") {
+            println_stderr!("cicada: printf: `{}': not a valid identifier", args[1]);
+            return 2;
+        }
+        var = Some(args[1].clone());
+        args.drain(0..2);
+    }
+    if args.first().map(String::as_str) == Some("--") {
+        args.remove(0);
+    }
+    if args.is_empty() {
+        println_stderr!("cicada: printf: usage: printf [-v var] format [arguments]");
+        return 2;
+    }
+
+    let format: Vec<char> = args.remove(0).chars().collect();
+    let mut printer = Printer {
+        args,
+        pos: 0,
+        output: Vec::new(),
+        status: 0,
+    };
+    loop {
+        let pos = printer.pos;
+        match printer.print_format(&format) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(e) => {
+                println_stderr!("cicada: printf: {}", e);
+                printer.status = 1;
+                break;
+            }
+        }
+        if printer.pos == pos || printer.pos >= printer.args.len() {
+            break;
+        }
+    }
+
+    match var {
+        Some(name) => {
+            sh.set_env(&name, &String::from_utf8_lossy(&printer.output));
+            printer.status
+        }
+        None => match write_output("printf", &printer.output) {
+            0 => printer.status,
+            x => x,
+        },
+    }
+}
+
+/// Write the output of `echo` and `printf` to stdout, returns the status.
+pub fn write_output(cmd: &str, output: &[u8]) -> i32 {
+    let mut stdout = io::stdout();
+    match stdout.write_all(output).and_then(|_| stdout.flush()) {
+        Ok(_) => 0,
+        // the reader is gone, e.g. `while true; do echo y; done | head -1`
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => 1,
+        Err(e) => {
+            println_stderr!("cicada: {}: write error: {}", cmd, e);
+            1
+        }
+    }
+}
+
+/// Expand the backslash escapes like `\n`, `\t`, `\0NNN` and `\xHH` for
+/// `echo -e` and `%b`. Also returns whether `\c` is met, after which no
+/// more output should be produced.
+pub fn expand_escapes(text: &str) -> (Vec<u8>, bool) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '\\' {
+            push_char(&mut result, chars[i]);
+            i += 1;
+            continue;
+        }
+        let (bytes, next, stop) = read_escape(&chars, i, false);
+        result.extend(bytes);
+        if stop {
+            return (result, true);
+        }
+        i = next;
+    }
+    (result, false)
+}
+
+fn push_char(bytes: &mut Vec<u8>, c: char) {
+    let mut buf = [0; 4];
+    bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+}
+
+/// Read the number of at most `max` digits in `radix` from `chars[i]`,
+/// returns it with the index after it.
+fn read_number(chars: &[char], i: usize, radix: u32, max: usize) -> (Option<u32>, usize) {
+    let mut value = None;
+    let mut j = i;
+    while j < chars.len() && j < i + max {
+        match chars[j].to_digit(radix) {
+            Some(x) => value = Some(value.unwrap_or(0) * radix + x),
+            None => break,
+        }
+        j += 1;
+    }
+    (value, j)
+}
+
+/// Read the width or precision at `chars[i]`, returns it with the index
+/// after it. `name` is used in the error, e.g. "width".
+fn read_width(chars: &[char], i: usize, name: &str) -> Result<(usize, usize), String> {
+    let mut j = i;
+    while j < chars.len() && chars[j].is_ascii_digit() {
+        j += 1;
+    }
+    let text: String = chars[i..j].iter().collect();
+    let value = text.chars().try_fold(0u64, |acc, c| {
+        acc.checked_mul(10)?.checked_add(c.to_digit(10)? as u64)
+    });
+    Ok((check_width(value, &text, name)?, j))
+}
+
+fn check_width(value: Option<u64>, text: &str, name: &str) -> Result<usize, String> {
+    match value {
+        Some(x) if x <= MAX_WIDTH => Ok(x as usize),
+        _ => Err(format!("{}: invalid {}", text, name)),
+    }
+}
+
+/// Read the escape at `chars[i]`, which is a backslash. Returns the bytes,
+/// the index after the escape, and whether it is `\c`. Octal numbers are
+/// `\NNN` in the format of `printf`, and `\0NNN` in `echo -e` and `%b`.
+fn read_escape(chars: &[char], i: usize, in_format: bool) -> (Vec<u8>, usize, bool) {
+    let c = match chars.get(i + 1) {
+        Some(x) => *x,
+        None => return (vec![b'\\'], i + 1, false),
+    };
+    let byte = match c {
+        'a' => 7,
+        'b' => 8,
+        'e' | 'E' => 27,
+        'f' => 12,
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'v' => 11,
+        '\\' => b'\\',
+        '"' | '\'' if in_format => c as u8,
+        'c' => return (Vec::new(), i + 2, true),
+        '0'..='7' if in_format || c == '0' => {
+            let start = if in_format { i + 1 } else { i + 2 };
+            let (value, next) = read_number(chars, start, 8, 3);
+            return (vec![value.unwrap_or(0) as u8], next, false);
+        }
+        'x' | 'u' | 'U' => {
+            let max = match c {
+                'x' => 2,
+                'u' => 4,
+                _ => 8,
+            };
+            let mut bytes = Vec::new();
+            let (value, next) = read_number(chars, i + 2, 16, max);
+            match value {
+                Some(x) if c == 'x' => bytes.push(x as u8),
+                Some(x) => push_char(&mut bytes, std::char::from_u32(x).unwrap_or('\u{fffd}')),
+                None => {
+                    bytes.push(b'\\');
+                    push_char(&mut bytes, c);
+                }
+            }
+            return (bytes, next, false);
+        }
+        _ => {
+            let mut bytes = vec![b'\\'];
+            push_char(&mut bytes, c);
+            return (bytes, i + 2, false);
+        }
+    };
+    (vec![byte], i + 2, false)
+}
+
+/// Quote the text for `%q`, so that it can be reused as shell input.
+fn quote(text: &str) -> String {
+    if text.is_empty() {
+        return String::from("''");
+    }
+    if text.chars().any(|c| c.is_control()) {
+        let mut result = String::from("$'");
+        for c in text.chars() {
+            match c {
+                '\n' => result.push_str("\\n"),
+                '\t' => result.push_str("\\t"),
+                '\r' => result.push_str("\\r"),
+                '\'' | '\\' => {
+                    result.push('\\');
+                    result.push(c);
+                }
+                c if c.is_control() => result.push_str(&format!("\\{:03o}", c as u32)),
+                c => result.push(c),
+            }
+        }
+        result.push('\'');
+        return result;
+    }
+
+    let mut result = String::new();
+    for c in text.chars() {
+        if !c.is_alphanumeric() && !"_-./:=@%+,".contains(c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Parse numbers like `42`, `-0x1f`, `017` and `'a'` (the code of `a`).
+fn parse_int(text: &str) -> Result<i64, String> {
+    let s = text.trim_start();
+    if s.starts_with('\'') || s.starts_with('"') {
+        return Ok(s.chars().nth(1).map_or(0, |c| c as i64));
+    }
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(x) => (true, x),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let result = if let Some(x) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(x, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse::<i64>()
+    };
+    match result {
+        Ok(x) if negative => Ok(-x),
+        Ok(x) => Ok(x),
+        Err(_) => Err(format!("{}: invalid number", text)),
+    }
+}
+
+fn parse_float(text: &str) -> Result<f64, String> {
+    let s = text.trim();
+    if s.starts_with('\'') || s.starts_with('"') {
+        return Ok(s.chars().nth(1).map_or(0.0, |c| c as u32 as f64));
+    }
+    match s.parse::<f64>() {
+        Ok(x) => Ok(x),
+        Err(_) => parse_int(text).map(|x| x as f64),
+    }
+}
+
+/// Format `x` like `%e` of C, e.g. `1.500000e+00`.
+fn format_exp(x: f64, precision: usize, upper: bool) -> String {
+    let text = format!("{:.*e}", precision, x);
+    let (mantissa, exp) = text.split_at(text.find('e').unwrap_or(text.len()));
+    let exp: i32 = exp.get(1..).and_then(|x| x.parse().ok()).unwrap_or(0);
+    let sign = if exp < 0 { '-' } else { '+' };
+    let e = if upper { 'E' } else { 'e' };
+    format!("{}{}{}{:02}", mantissa, e, sign, exp.abs())
+}
+
+/// Format `x` like `%g` of C, which takes the shorter one of `%f` and `%e`.
+fn format_general(x: f64, precision: usize, alt: bool, upper: bool) -> String {
+    let precision = precision.max(1);
+    let exp = if x == 0.0 {
+        0
+    } else {
+        let text = format!("{:.*e}", precision - 1, x);
+        text[text.find('e').unwrap_or(0) + 1..].parse().unwrap_or(0)
+    };
+    let mut result = if exp < -4 || exp >= precision as i32 {
+        format_exp(x, precision - 1, upper)
+    } else {
+        format!("{:.*}", (precision as i32 - 1 - exp) as usize, x)
+    };
+    if !alt && result.contains('.') {
+        // strip the trailing zeros of the fraction
+        let split = result.find(['e', 'E']).unwrap_or(result.len());
+        let exp_part = result[split..].to_string();
+        let fraction = result[..split].trim_end_matches('0').trim_end_matches('.');
+        result = format!("{}{}", fraction, exp_part);
+    }
+    result
+}
+
+impl Printer {
+    fn next_arg(&mut self) -> Option<String> {
+        let arg = self.args.get(self.pos).cloned();
+        if arg.is_some() {
+            self.pos += 1;
+        }
+        arg
+    }
+
+    fn next_int(&mut self) -> i64 {
+        let arg = match self.next_arg() {
+            Some(x) => x,
+            None => return 0,
+        };
+        match parse_int(&arg) {
+            Ok(x) => x,
+            Err(e) => {
+                println_stderr!("cicada: printf: {}", e);
+                self.status = 1;
+                0
+            }
+        }
+    }
+
+    fn next_float(&mut self) -> f64 {
+        let arg = match self.next_arg() {
+            Some(x) => x,
+            None => return 0.0,
+        };
+        match parse_float(&arg) {
+            Ok(x) => x,
+            Err(e) => {
+                println_stderr!("cicada: printf: {}", e);
+                self.status = 1;
+                0.0
+            }
+        }
+    }
+
+    /// Print the arguments with the format once, returns whether the
+    /// output is stopped by `\c`.
+    fn print_format(&mut self, format: &[char]) -> Result<bool, String> {
+        let len = format.len();
+        let mut i = 0;
+        while i < len {
+            let c = format[i];
+            if c == '\\' {
+                let (bytes, next, stop) = read_escape(format, i, true);
+                self.output.extend(bytes);
+                if stop {
+                    return Ok(true);
+                }
+                i = next;
+                continue;
+            }
+            if c != '%' {
+                push_char(&mut self.output, c);
+                i += 1;
+                continue;
+            }
+            if format.get(i + 1) == Some(&'%') {
+                self.output.push(b'%');
+                i += 2;
+                continue;
+            }
+
+            let start = i;
+            let mut spec = Spec::default();
+            i += 1;
+            while i < len && "-+ #0".contains(format[i]) {
+                match format[i] {
+                    '-' => spec.left = true,
+                    '+' => spec.plus = true,
+                    ' ' => spec.space = true,
+                    '#' => spec.alt = true,
+                    _ => spec.zero = true,
+                }
+                i += 1;
+            }
+            if format.get(i) == Some(&'*') {
+                let width = self.next_int();
+                spec.left |= width < 0;
+                let value = Some(width.unsigned_abs());
+                spec.width = check_width(value, &width.to_string(), "width")?;
+                i += 1;
+            } else {
+                let (width, next) = read_width(format, i, "width")?;
+                spec.width = width;
+                i = next;
+            }
+            if format.get(i) == Some(&'.') {
+                i += 1;
+                if format.get(i) == Some(&'*') {
+                    // a negative precision is taken as omitted, like in C
+                    let precision = self.next_int();
+                    if precision >= 0 {
+                        let text = precision.to_string();
+                        let value = Some(precision as u64);
+                        spec.precision = Some(check_width(value, &text, "precision")?);
+                    }
+                    i += 1;
+                } else {
+                    let (precision, next) = read_width(format, i, "precision")?;
+                    spec.precision = Some(precision);
+                    i = next;
+                }
+            }
+            spec.conv = match format.get(i) {
+                Some(x) => *x,
+                None => {
+                    let text: String = format[start..].iter().collect();
+                    return Err(format!("`{}': missing format character", text));
+                }
+            };
+            i += 1;
+            if self.print_arg(&spec)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Print the next argument with the conversion, returns whether the
+    /// output is stopped by `\c` in the argument of `%b`.
+    fn print_arg(&mut self, spec: &Spec) -> Result<bool, String> {
+        let mut stop = false;
+        let text = match spec.conv {
+            's' | 'q' | 'c' | 'b' => {
+                let arg = self.next_arg().unwrap_or_default();
+                let mut text = match spec.conv {
+                    's' => arg,
+                    'q' => quote(&arg),
+                    'c' => arg.chars().take(1).collect(),
+                    _ => {
+                        let (bytes, is_stop) = expand_escapes(&arg);
+                        stop = is_stop;
+                        if spec.width == 0 && spec.precision.is_none() {
+                            self.output.extend(bytes);
+                            return Ok(stop);
+                        }
+                        String::from_utf8_lossy(&bytes).to_string()
+                    }
+                };
+                if let Some(precision) = spec.precision {
+                    text = text.chars().take(precision).collect();
+                }
+                pad(spec, "", "", &text, false)
+            }
+            'd' | 'i' => {
+                let value = self.next_int();
+                let sign = sign_of(spec, value < 0);
+                let digits = value.unsigned_abs().to_string();
+                format_digits(spec, sign, "", digits)
+            }
+            'u' | 'o' | 'x' | 'X' => {
+                // negative numbers are taken as unsigned, like in C
+                let value = self.next_int() as u64;
+                let digits = match spec.conv {
+                    'u' => value.to_string(),
+                    'o' => format!("{:o}", value),
+                    'x' => format!("{:x}", value),
+                    _ => format!("{:X}", value),
+                };
+                let prefix = match spec.conv {
+                    'x' if spec.alt && value != 0 => "0x",
+                    'X' if spec.alt && value != 0 => "0X",
+                    _ => "",
+                };
+                let mut digits = digits;
+                if spec.conv == 'o' && spec.alt && !digits.starts_with('0') {
+                    digits.insert(0, '0');
+                }
+                format_digits(spec, "", prefix, digits)
+            }
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                let value = self.next_float();
+                let upper = spec.conv.is_ascii_uppercase();
+                let sign = sign_of(spec, value.is_sign_negative() && !value.is_nan());
+                let x = value.abs();
+                let precision = spec.precision.unwrap_or(6);
+                let mut body = if x.is_finite() {
+                    match spec.conv {
+                        'f' | 'F' => format!("{:.*}", precision, x),
+                        'e' | 'E' => format_exp(x, precision, upper),
+                        _ => format_general(x, precision, spec.alt, upper),
+                    }
+                } else if x.is_nan() {
+                    String::from("nan")
+                } else {
+                    String::from("inf")
+                };
+                if spec.alt && precision == 0 && "fF".contains(spec.conv) {
+                    body.push('.');
+                }
+                if upper {
+                    body = body.to_uppercase();
+                }
+                pad(spec, sign, "", &body, x.is_finite())
+            }
+            c => return Err(format!("`{}': invalid format character", c)),
+        };
+        self.output.extend(text.as_bytes());
+        Ok(stop)
+    }
+}
+
+fn sign_of(spec: &Spec, negative: bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+/// Format integers, the precision is the minimum number of digits.
+fn format_digits(spec: &Spec, sign: &str, prefix: &str, digits: String) -> String {
+    let digits = match spec.precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(x) if digits.len() < x => format!("{}{}", "0".repeat(x - digits.len()), digits),
+        _ => digits,
+    };
+    pad(spec, sign, prefix, &digits, spec.precision.is_none())
+}
+
+/// Pad the text to the width, with zeros after the sign for numbers
+/// when the flag `0` is given.
+fn pad(spec: &Spec, sign: &str, prefix: &str, body: &str, numeric: bool) -> String {
+    let len = sign.len() + prefix.len() + body.chars().count();
+    if len >= spec.width {
+        return format!("{}{}{}", sign, prefix, body);
+    }
+    let fill = spec.width - len;
+    if spec.left {
+        format!("{}{}{}{}", sign, prefix, body, " ".repeat(fill))
+    } else if spec.zero && numeric {
+        format!("{}{}{}{}", sign, prefix, "0".repeat(fill), body)
+    } else {
+        format!("{}{}{}{}", " ".repeat(fill), sign, prefix, body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use crate::scripting::{check, run_lines};
+    use crate::shell;
+
+    #[test]
+    fn test_run_printf() {
+        let mut sh = shell::Shell::new();
+        let v = [
+            ("S=$(printf '%s-%5s|%-3s|%.2s' a b c def)", "a-    b|c  |de"),
+            (
+                "S=$(printf '%d %03d %+d %x %#X %o' 42 7 5 255 255 8)",
+                "42 007 +5 ff 0XFF 10",
+            ),
+            (
+                "S=$(printf '%.2f %e %g %g' 3.14159 1234.5 0.0001 1e10)",
+                "3.14 1.234500e+03 0.0001 1e+10",
+            ),
+            ("S=$(printf '%s=%s;' a 1 b 2 c)", "a=1;b=2;c=;"),
+            ("S=$(printf '%b|%q' 'x\\ty' 'a b')", "x\ty|a\\ b"),
+            (
+                "printf -v S '%*d|%c|%.*f' 4 1 xyz -1 0.5",
+                "   1|x|0.500000",
+            ),
+            ("S=$(printf '\\101\\x42%%')", "AB%"),
+            ("printf -v S '%s-%d' a 2", "a-2"),
+            ("printf -v S %d abc; S=$S$?", "01"),
+        ];
+        check(&mut sh, &v);
+
+        let file = env::temp_dir().join(format!("cicada-printf-{}.txt", process::id()));
+        let file = file.to_string_lossy().to_string();
+        let text = format!("echo a > {0}; printf '%s\\n' b >> {0}; S=$(cat {0})", file);
+        run_lines(&mut sh, &text);
+        assert_eq!(sh.get_env("S"), Some("a\nb".to_string()));
+        fs::remove_file(&file).ok();
+        assert_eq!(run_lines(&mut sh, "printf '%z' 1"), 1);
+        assert_eq!(run_lines(&mut sh, "printf"), 2);
+        assert_eq!(run_lines(&mut sh, "printf '%5000000000d' 1"), 1);
+        assert_eq!(run_lines(&mut sh, "printf '%*d' 5000000000 1"), 1);
+        assert_eq!(run_lines(&mut sh, "printf '%.*f' 5000000000 1"), 1);
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Write};
+use std::mem;
+
+use libc;
+
+use crate::shell;
+use crate::tools;
+use crate::types::{self, Tokens};
+
+/// Status of `read` when it times out, the same as bash.
+const TIMEOUT_STATUS: i32 = 142;
+
+#[derive(Default)]
+struct Options {
+    raw: bool,
+    silent: bool,
+    prompt: String,
+    timeout: Option<f64>,
+    nchars: Option<usize>,
+    delim: Option<u8>,
+    array: Option<String>,
+    names: Vec<String>,
+}
+
+/// `read [-r] [-p prompt] [-s] [-t timeout] [-n N] [-d delim] [-a arr]
+/// [name ...]` reads a line from stdin, and splits it by `$IFS` into the
+/// variables. The last one gets the rest of the line. Without names, the
+/// line is kept in `$REPLY`. It returns 1 at the end of input. `envs` are
+/// the assignments before it, e.g. `IFS=: read a b`.
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens, envs: &HashMap<String, String>) -> i32 {
+    let opts = match parse_options(tokens) {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: read: {}", e);
+            println_stderr!(
+                "usage: read [-rs] [-a array] [-d delim] [-n nchars] [-p prompt] [-t timeout] [name ...]"
+            );
+            return 2;
+        }
+    };
+    for name in opts.names.iter().chain(opts.array.iter()) {
+        if !tools::re_contains(name, r"^[a-zA-Z_][a-zA-Z0-9_]*$") {
+            println_stderr!("cicada: read: `{}': not a valid identifier", name);
+            return 1;
+        }
+    }
+
+    let isatty = unsafe { libc::isatty(0) == 1 };
+    if isatty && !opts.prompt.is_empty() {
+        eprint!("{}", opts.prompt);
+        io::stderr().flush().ok();
+    }
+    let saved_term = if isatty { set_terminal(&opts) } else { None };
+    let (text, status) = read_input(&opts);
+    if let Some(term) = saved_term {
+        unsafe {
+            libc::tcsetattr(0, libc::TCSANOW, &term);
+        }
+    }
+
+    let ifs = envs
+        .get("IFS")
+        .cloned()
+        .or_else(|| sh.get_env("IFS"))
+        .or_else(|| env::var("IFS").ok())
+        .unwrap_or_else(|| String::from(" \t\n"));
+    if let Some(name) = &opts.array {
+        let mut array = types::Array::Indexed(Default::default());
+        for field in split_fields(&text, &ifs, 0) {
+            array.push(&field);
+        }
+        sh.set_array(name, array);
+    } else if opts.names.is_empty() {
+        sh.set_env("REPLY", &text);
+    } else {
+        let fields = split_fields(&text, &ifs, opts.names.len());
+        for (i, name) in opts.names.iter().enumerate() {
+            let value = fields.get(i).map_or("", |x| x.as_str());
+            sh.set_env(name, value);
+        }
+    }
+    status
+}
+
+fn parse_options(tokens: &Tokens) -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut i = 1;
+    while i < tokens.len() {
+        let arg = &tokens[i].1;
+        i += 1;
+        if arg == "--" {
+            break;
+        }
+        if !arg.starts_with('-') || arg.len() == 1 {
+            i -= 1;
+            break;
+        }
+
+        for (j, c) in arg.char_indices().skip(1) {
+            if c == 'r' {
+                opts.raw = true;
+                continue;
+            }
+            if c == 's' {
+                opts.silent = true;
+                continue;
+            }
+            if !"ptnda".contains(c) {
+                return Err(format!("-{}: invalid option", c));
+            }
+
+            // the value could be in the same arg like `-n1`, or the next
+            let value = if j + 1 < arg.len() {
+                arg[j + 1..].to_string()
+            } else if i < tokens.len() {
+                i += 1;
+                tokens[i - 1].1.clone()
+            } else {
+                return Err(format!("-{}: option requires an argument", c));
+            };
+            match c {
+                'p' => opts.prompt = value,
+                't' => match value.parse::<f64>() {
+                    Ok(x) if x >= 0.0 => opts.timeout = Some(x),
+                    _ => return Err(format!("{}: invalid timeout specification", value)),
+                },
+                'n' => match value.parse::<usize>() {
+                    Ok(x) => opts.nchars = Some(x),
+                    Err(_) => return Err(format!("{}: invalid number", value)),
+                },
+                // `-d ''` reads until a NUL char
+                'd' => opts.delim = Some(value.bytes().next().unwrap_or(0)),
+                _ => opts.array = Some(value),
+            }
+            break;
+        }
+    }
+    opts.names = tokens[i..].iter().map(|x| x.1.clone()).collect();
+    Ok(opts)
+}
+
+/// Turn off echo for `-s`, and read chars without waiting for the line
+/// for `-n` and `-d`. Returns the settings to restore.
+fn set_terminal(opts: &Options) -> Option<libc::termios> {
+    let by_char = opts.nchars.is_some() || opts.delim.is_some();
+    if !opts.silent && !by_char {
+        return None;
+    }
+    unsafe {
+        let mut term: libc::termios = mem::zeroed();
+        if libc::tcgetattr(0, &mut term) != 0 {
+            return None;
+        }
+        let saved = term;
+        if opts.silent {
+            term.c_lflag &= !libc::ECHO;
+        }
+        if by_char {
+            term.c_lflag &= !libc::ICANON;
+            term.c_cc[libc::VMIN] = 1;
+            term.c_cc[libc::VTIME] = 0;
+        }
+        libc::tcsetattr(0, libc::TCSANOW, &term);
+        Some(saved)
+    }
+}
+
+/// Read one byte from stdin, waiting at most until `deadline` if given.
+/// The input is read byte by byte, so that the rest of it is left for
+/// the following commands, e.g. in `while read line; do ...; done`.
+fn read_byte(deadline: Option<f64>) -> Result<Option<u8>, i32> {
+    loop {
+        if let Some(deadline) = deadline {
+            // the deadline counts even when the input keeps coming
+            let left = deadline - now();
+            if left <= 0.0 {
+                return Err(TIMEOUT_STATUS);
+            }
+            let left = (left * 1000.0).ceil() as i32;
+            let mut fds = libc::pollfd {
+                fd: 0,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let rc = unsafe { libc::poll(&mut fds, 1, left) };
+            if rc == 0 {
+                return Err(TIMEOUT_STATUS);
+            }
+        }
+
+        let mut byte = 0u8;
+        let rc = unsafe { libc::read(0, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+        if rc == 1 {
+            return Ok(Some(byte));
+        }
+        if rc == 0 {
+            return Ok(None);
+        }
+        if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return Err(1);
+        }
+    }
+}
+
+fn now() -> f64 {
+    let spec = time::get_time();
+    spec.sec as f64 + spec.nsec as f64 / 1_000_000_000.0
+}
+
+/// Read the input until the delimiter (or N chars for `-n`), returns it
+/// with the status: 0 for success, 1 for the end of input, and 142 for
+/// timeouts.
+fn read_input(opts: &Options) -> (String, i32) {
+    let deadline = opts.timeout.map(|x| now() + x);
+    let delim = opts.delim.unwrap_or(b'\n');
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut count = 0;
+    let mut status = 0;
+    loop {
+        if opts.nchars.is_some_and(|n| count >= n) {
+            break;
+        }
+        let byte = match read_byte(deadline) {
+            Ok(Some(x)) => x,
+            Ok(None) => {
+                status = 1;
+                break;
+            }
+            Err(x) => {
+                status = x;
+                break;
+            }
+        };
+        if byte == delim {
+            break;
+        }
+
+        if byte == b'\\' && !opts.raw {
+            // without `-r`, backslash escapes the next char, and the
+            // backslash-newline pairs are removed
+            match read_byte(deadline) {
+                Ok(Some(b'\n')) => continue,
+                Ok(Some(x)) => bytes.push(x),
+                Ok(None) => {
+                    status = 1;
+                    break;
+                }
+                Err(x) => {
+                    status = x;
+                    break;
+                }
+            }
+        } else {
+            bytes.push(byte);
+        }
+
+        // keep the multi-byte chars whole for `-n`
+        let extra = match bytes[bytes.len() - 1] {
+            x if x >= 0xf0 => 3,
+            x if x >= 0xe0 => 2,
+            x if x >= 0xc0 => 1,
+            _ => 0,
+        };
+        for _ in 0..extra {
+            match read_byte(deadline) {
+                Ok(Some(x)) => bytes.push(x),
+                _ => break,
+            }
+        }
+        count += 1;
+    }
+    (String::from_utf8_lossy(&bytes).to_string(), status)
+}
+
+/// Split `text` into fields by the chars in `ifs`. Whitespaces in it are
+/// trimmed around the fields, while the others make empty fields when
+/// there are more of them in a row, e.g. `a,,b`. With `max` fields, the
+/// last one gets the rest of the text.
+fn split_fields(text: &str, ifs: &str, max: usize) -> Vec<String> {
+    let is_sep = |c: char| ifs.contains(c);
+    let is_space = |c: char| is_sep(c) && c.is_whitespace();
+    let chars: Vec<char> = text.chars().collect();
+    let len = chars.len();
+
+    let mut fields = Vec::new();
+    let mut i = 0;
+    while i < len && is_space(chars[i]) {
+        i += 1;
+    }
+    while i < len {
+        if max > 0 && fields.len() + 1 == max {
+            let mut end = len;
+            while end > i && is_space(chars[end - 1]) {
+                end -= 1;
+            }
+            fields.push(chars[i..end].iter().collect());
+            break;
+        }
+
+        let start = i;
+        while i < len && !is_sep(chars[i]) {
+            i += 1;
+        }
+        fields.push(chars[start..i].iter().collect());
+        while i < len && is_space(chars[i]) {
+            i += 1;
+        }
+        if i < len && is_sep(chars[i]) {
+            i += 1;
+            while i < len && is_space(chars[i]) {
+                i += 1;
+            }
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use crate::scripting::check;
+    use crate::shell;
+
+    #[test]
+    fn test_run_read() {
+        let mut sh = shell::Shell::new();
+        let file = std::env::temp_dir().join(format!("cicada-read-{}.txt", process::id()));
+        std::fs::write(&file, "one  two three\nfour\n").unwrap();
+        let file = file.to_string_lossy().to_string();
+
+        let v = [
+            (format!("read x y < {}; S=\"$x|$y\"", file), "one|two three"),
+            (format!("read < {}; S=$REPLY", file), "one  two three"),
+            (format!("read -a arr < {}; S=${{arr[2]}}", file), "three"),
+            (format!("read -n 6 x < {}; S=$x", file), "one  t"),
+            (format!("read -d t x < {}; S=$x", file), "one"),
+            (format!("IFS=e read x y < {}; S=$y", file), "  two three"),
+            (format!("{{ read x; read y; }} < {}; S=$y", file), "four"),
+            (
+                format!("while read x; do S=\"$S$x.\"; done < {}", file),
+                "one  two three.four.",
+            ),
+            (
+                "S=$(printf 'a,,b\\n' | { IFS=, read x y z; echo \"$x|$y|$z\"; })".to_string(),
+                "a||b",
+            ),
+            (
+                "S=$(printf 'a\\\\tb\\n' | { read x; read -r y; echo $x; })".to_string(),
+                "atb",
+            ),
+            (
+                "S=$(echo foo | { read x; echo $x$x; })".to_string(),
+                "foofoo",
+            ),
+            ("read x < /dev/null; S=$?".to_string(), "1"),
+            ("read 1x < /dev/null; S=$?".to_string(), "1"),
+            ("read -n x < /dev/null; S=$?".to_string(), "2"),
+            (
+                "S=$(yes | tr -d '\\n' | { read -t 0.2 x; echo $?; })".to_string(),
+                "142",
+            ),
+        ];
+        check(&mut sh, &v);
+        std::fs::remove_file(&file).ok();
+    }
+}
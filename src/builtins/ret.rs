@@ -0,0 +1,30 @@
+use std::io::Write;
+
+use crate::shell;
+use crate::types::Tokens;
+
+/// `return [N]`, stop the current function with status N (or the status
+/// of the last command).
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    if sh.scopes.is_empty() {
+        println_stderr!("cicada: return: can only `return' from a function");
+        return 1;
+    }
+    if tokens.len() > 2 {
+        println_stderr!("cicada: return: too many arguments");
+        return 1;
+    }
+
+    let mut status = sh.previous_status;
+    if tokens.len() == 2 {
+        match tokens[1].1.parse::<i32>() {
+            Ok(x) => status = x & 0xff,
+            Err(_) => {
+                println_stderr!("cicada: return: {}: numeric argument required", tokens[1].1);
+                status = 2;
+            }
+        }
+    }
+    sh.returning = true;
+    status
+}
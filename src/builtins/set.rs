@@ -0,0 +1,149 @@
+use std::io::Write;
+
+use crate::shell;
+use crate::types::Tokens;
+
+/// The options of `set -o NAME`, and their short forms like `set -e`.
+const OPTIONS: [(&str, Option<char>); 5] = [
+    ("errexit", Some('e')),
+    ("noclobber", Some('C')),
+    ("nounset", Some('u')),
+    ("pipefail", None),
+    ("xtrace", Some('x')),
+];
+
+/// `set -- a b c` makes `a`, `b` and `c` the positional parameters, i.e.
+/// `$1`, `$2` and `$3`. Options are turned on with `-` and off with `+`,
+/// e.g. `set -eu -o pipefail`. Without arguments, it prints the shell
+/// variables.
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    if tokens.len() == 1 {
+        let mut names: Vec<&String> = sh.envs.keys().collect();
+        names.sort();
+        for name in names {
+            println!("{}={}", name, sh.envs[name]);
+        }
+        return 0;
+    }
+
+    let mut i = 1;
+    let mut set_args = false;
+    while i < tokens.len() {
+        let arg = &tokens[i].1;
+        if arg == "--" {
+            set_args = true;
+            i += 1;
+            break;
+        }
+        let on = arg.starts_with('-');
+        if !on && !arg.starts_with('+') {
+            break;
+        }
+        if arg.len() == 1 {
+            i += 1;
+            break;
+        }
+
+        for c in arg[1..].chars() {
+            let name = if c == 'o' {
+                i += 1;
+                match tokens.get(i) {
+                    Some(x) => x.1.as_str(),
+                    None => {
+                        print_options(sh, on);
+                        return 0;
+                    }
+                }
+            } else {
+                match OPTIONS.iter().find(|x| x.1 == Some(c)) {
+                    Some(x) => x.0,
+                    None => {
+                        println_stderr!("cicada: set: -{}: invalid option", c);
+                        return 1;
+                    }
+                }
+            };
+            match sh.options.get_mut(name) {
+                Some(x) => *x = on,
+                None => {
+                    println_stderr!("cicada: set: {}: invalid option name", name);
+                    return 1;
+                }
+            }
+        }
+        i += 1;
+    }
+
+    if set_args || i < tokens.len() {
+        let mut args = vec![sh.args.first().cloned().unwrap_or_default()];
+        args.extend(tokens[i..].iter().map(|x| x.1.clone()));
+        sh.args = args;
+    }
+    0
+}
+
+/// `set -o` lists the options, `set +o` prints them as commands.
+fn print_options(sh: &mut shell::Shell, on: bool) {
+    for (name, _) in OPTIONS.iter() {
+        let value = sh.options.get_mut(name).is_some_and(|x| *x);
+        if on {
+            println!("{:<15}\t{}", name, if value { "on" } else { "off" });
+        } else {
+            println!("set {}o {}", if value { '-' } else { '+' }, name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use crate::scripting::{check, run_lines};
+    use crate::shell;
+
+    #[test]
+    fn test_run_shell_options() {
+        let mut sh = shell::Shell::new();
+        let v = [
+            ("S=$(set -e; echo a; false; echo b)", "a"),
+            (
+                "S=$(set -e; f() { false; echo a; }; f || echo b; ! f; echo c)",
+                "a\na\nc",
+            ),
+            (
+                "S=$(set -e; if false; then :; fi; while false; do :; done; echo a)",
+                "a",
+            ),
+            ("set -o pipefail; false | true; S=$?; set +o pipefail", "1"),
+            ("false | true; S=$?", "0"),
+            ("set -u; echo $CICADA_NOT_DEFINED; S=$?; set +u", "1"),
+            ("set -u; S=${CICADA_NOT_DEFINED:-a}; set +u", "a"),
+        ];
+        check(&mut sh, &v);
+
+        let file = std::env::temp_dir().join(format!("cicada-noclobber-{}", process::id()));
+        let line = "set -C; echo a > F; echo b > F; S=$?; echo c >| F; S=$S$(cat F); set +C; rm F";
+        sh.set_env("S", "");
+        run_lines(&mut sh, &line.replace("F", &file.to_string_lossy()));
+        assert_eq!(sh.get_env("S"), Some("1c".to_string()));
+
+        // non-interactive shells exit on unbound variables, and `${foo:?}`
+        sh.non_interactive = true;
+        run_lines(
+            &mut sh,
+            "S=$(set -u; echo a; echo $CICADA_NOT_DEFINED; echo b)",
+        );
+        assert_eq!(sh.get_env("S"), Some("a".to_string()));
+        run_lines(&mut sh, "S=$(echo a; echo ${CICADA_NOT_DEFINED:?}; echo b)");
+        assert_eq!(sh.get_env("S"), Some("a".to_string()));
+        sh.non_interactive = false;
+
+        assert_eq!(run_lines(&mut sh, "set -eux -o pipefail +x"), 0);
+        assert!(sh.options.errexit && sh.options.nounset && sh.options.pipefail);
+        assert!(!sh.options.xtrace && !sh.options.noclobber);
+        assert_eq!(run_lines(&mut sh, "set +eu -C"), 0);
+        assert!(!sh.options.errexit && !sh.options.nounset && sh.options.noclobber);
+        assert_eq!(run_lines(&mut sh, "set -o foo"), 1);
+        assert_eq!(run_lines(&mut sh, "set -q"), 1);
+    }
+}
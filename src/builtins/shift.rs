@@ -0,0 +1,61 @@
+use std::io::Write;
+
+use crate::shell;
+use crate::types::Tokens;
+
+/// `shift N` drops the first N positional parameters, i.e. `$N+1` becomes
+/// `$1`. N is 1 by default.
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    if tokens.len() > 2 {
+        println_stderr!("cicada: shift: too many arguments");
+        return 1;
+    }
+
+    let mut num = 1;
+    if tokens.len() == 2 {
+        match tokens[1].1.parse::<usize>() {
+            Ok(x) => num = x,
+            Err(_) => {
+                println_stderr!("cicada: shift: {}: numeric argument required", tokens[1].1);
+                return 1;
+            }
+        }
+    }
+    if num > sh.args.len().saturating_sub(1) {
+        println_stderr!("cicada: shift: {}: shift count out of range", num);
+        return 1;
+    }
+    if num > 0 {
+        sh.args.drain(1..=num);
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scripting::check;
+    use crate::shell;
+
+    #[test]
+    fn test_run_positional_params() {
+        let mut sh = shell::Shell::new();
+        sh.args = vec!["foo.sh".to_string()];
+        let v = [
+            (
+                "set -- a 'b c' d; for i in \"$@\"; do S=$S-$i; done",
+                "-a-b c-d",
+            ),
+            ("set -- a 'b c'; for i in $*; do S=$S-$i; done", "-a-b-c"),
+            ("set -- a b c; shift; S=\"$# $1 $*\"", "2 b b c"),
+            ("set -- a b c; shift 2; shift 2; S=\"$? $1\"", "1 c"),
+            ("set -- 1 2 3 4 5 6 7 8 9 10; S=\"$10 ${10}\"", "10 10"),
+            ("set --; for i in \"$@\"; do S=x; done; S=$S$#", "0"),
+            (
+                "f() { shift; S=\"$1 $#\"; }; set -- a; f x 'y z'; S=\"$S $1\"",
+                "y z 1 a",
+            ),
+        ];
+        check(&mut sh, &v);
+        assert_eq!(sh.args[0], "foo.sh");
+    }
+}
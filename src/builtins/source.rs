@@ -0,0 +1,105 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::scripting;
+use crate::shell;
+use crate::types::Tokens;
+
+/// `source file [args]` (or `. file [args]`) runs the commands in the file
+/// in current shell, so that its variables, aliases and functions are
+/// kept. The args become `$1`, `$2`, ... while the file runs.
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    let cmd = &tokens[0].1;
+    if tokens.len() < 2 {
+        println_stderr!("cicada: {}: filename argument required", cmd);
+        return 2;
+    }
+
+    let file_name = &tokens[1].1;
+    let path = match find_file(file_name) {
+        Some(x) => x,
+        None => {
+            println_stderr!("cicada: {}: {}: No such file or directory", cmd, file_name);
+            return 1;
+        }
+    };
+    let args: Vec<String> = tokens[2..].iter().map(|x| x.1.clone()).collect();
+    run_file(sh, &path, &args)
+}
+
+/// Files without `/` are searched in `$PATH` first, then in current
+/// directory.
+fn find_file(file_name: &str) -> Option<String> {
+    if !file_name.contains('/') {
+        if let Ok(paths) = env::var("PATH") {
+            for dir in paths.split(':') {
+                let path = Path::new(dir).join(file_name);
+                if path.is_file() {
+                    return Some(path.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+    if Path::new(file_name).is_file() {
+        return Some(file_name.to_string());
+    }
+    None
+}
+
+/// Run the commands in file `path` in current shell, with `args` as the
+/// positional parameters if there are any. Rc files are split by their
+/// `include` lines instead, see `rcfile::load_file()`.
+pub fn run_file(sh: &mut shell::Shell, path: &str, args: &[String]) -> i32 {
+    let text = match fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: {}: {}", path, e);
+            return 1;
+        }
+    };
+
+    if args.is_empty() {
+        return scripting::run_lines(sh, &text);
+    }
+    let mut args_new = vec![sh.args.first().cloned().unwrap_or_default()];
+    args_new.extend(args.iter().cloned());
+    let args_saved = std::mem::replace(&mut sh.args, args_new);
+    let status = scripting::run_lines(sh, &text);
+    sh.args = args_saved;
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use crate::scripting::run_lines;
+    use crate::shell;
+
+    #[test]
+    fn test_run_source() {
+        let mut sh = shell::Shell::new();
+        let file = std::env::temp_dir().join(format!("cicada-source-{}.sh", process::id()));
+        let text =
+            "alias foo='echo foo'\nS=\"$S $# $1\"\nif true; then\n  f() { S=\"$S f$1\"; }\nfi\n";
+        std::fs::write(&file, text).unwrap();
+        let file = file.to_string_lossy().to_string();
+
+        sh.set_env("S", "");
+        assert_eq!(
+            run_lines(&mut sh, &format!("source {} a 'b c'; f $#", file)),
+            0
+        );
+        assert_eq!(sh.get_env("S"), Some(" 2 a f0".to_string()));
+        assert_eq!(sh.get_alias_content("foo"), Some("echo foo".to_string()));
+        sh.set_env("S", "");
+        assert_eq!(run_lines(&mut sh, &format!(". {}", file)), 0);
+        assert_eq!(sh.get_env("S"), Some(" 0 ".to_string()));
+        std::fs::remove_file(&file).ok();
+
+        assert_eq!(run_lines(&mut sh, &format!("source {}", file)), 1);
+        assert_eq!(run_lines(&mut sh, "source"), 2);
+    }
+}
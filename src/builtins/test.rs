@@ -0,0 +1,460 @@
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+
+use glob;
+use libc;
+use regex::{self, Regex};
+
+use crate::parsers;
+use crate::shell;
+use crate::types::{self, Tokens};
+
+const UNARY_OPS: [&str; 11] = [
+    "-d", "-e", "-f", "-h", "-L", "-n", "-r", "-s", "-w", "-x", "-z",
+];
+
+const BINARY_OPS: [&str; 15] = [
+    "=", "==", "!=", "<", ">", "=~", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-ef", "-nt", "-ot",
+];
+
+/// How an operand of `[[ ]]` is expanded.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    Pattern,
+    Regex,
+}
+
+/// Evaluates the expressions of both `test` and `[[ ]]`. For `test`, the
+/// words are the arguments already expanded. For `[[ ]]`, they are the
+/// words in the script, which are expanded only when used, and without
+/// splitting, so `[[ -n $x ]]` works even when `$x` is empty.
+struct Expr<'a> {
+    sh: &'a mut shell::Shell,
+    words: Vec<String>,
+    pos: usize,
+    is_cond: bool,
+    // operands skipped by `&&` and `||` are not expanded
+    skip: bool,
+}
+
+/// `test EXPR` and `[ EXPR ]`, returns 0 if the expression is true, 1 if
+/// it is false, and 2 for errors.
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    let cmd = &tokens[0].1;
+    let mut words: Vec<String> = tokens[1..].iter().map(|x| x.1.clone()).collect();
+    if cmd == "[" {
+        if words.last().map(String::as_str) != Some("]") {
+            println_stderr!("cicada: [: missing `]'");
+            return 2;
+        }
+        words.pop();
+    }
+
+    let mut expr = Expr {
+        sh,
+        words,
+        pos: 0,
+        is_cond: false,
+        skip: false,
+    };
+    match expr.eval_test() {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            println_stderr!("cicada: {}: {}", cmd, e);
+            2
+        }
+    }
+}
+
+/// Run `[[ ... ]]` with its words, e.g. `["-f", "$x", "&&", "$y", "==",
+/// "a*"]`.
+pub fn run_cond(sh: &mut shell::Shell, words: &[String]) -> i32 {
+    let mut expr = Expr {
+        sh,
+        words: words.to_vec(),
+        pos: 0,
+        is_cond: true,
+        skip: false,
+    };
+    match expr.eval_all() {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            println_stderr!("cicada: [[: {}", e);
+            2
+        }
+    }
+}
+
+impl Expr<'_> {
+    fn peek(&self, offset: usize) -> Option<&str> {
+        self.words.get(self.pos + offset).map(|x| x.as_str())
+    }
+
+    fn left(&self) -> usize {
+        self.words.len() - self.pos
+    }
+
+    fn is_binary_op(&self, offset: usize) -> bool {
+        match self.peek(offset) {
+            Some("=~") => self.is_cond,
+            Some(x) => BINARY_OPS.contains(&x),
+            None => false,
+        }
+    }
+
+    fn next_word(&mut self) -> Result<String, String> {
+        match self.peek(0) {
+            Some(x) => {
+                let word = x.to_string();
+                self.pos += 1;
+                Ok(word)
+            }
+            None => Err(String::from("argument expected")),
+        }
+    }
+
+    fn eval_all(&mut self) -> Result<bool, String> {
+        let result = self.eval_or()?;
+        match self.peek(0) {
+            Some(x) => Err(format!("{}: unexpected argument", x)),
+            None => Ok(result),
+        }
+    }
+
+    /// `test` decides the meaning of the arguments by their number first,
+    /// e.g. `test -n` is true, as `-n` is a non-empty string there.
+    fn eval_test(&mut self) -> Result<bool, String> {
+        let words = &self.words;
+        match words.len() {
+            0 => Ok(false),
+            1 => Ok(!words[0].is_empty()),
+            2 if words[0] == "!" => Ok(words[1].is_empty()),
+            2 if UNARY_OPS.contains(&words[0].as_str()) => {
+                let op = words[0].clone();
+                let value = words[1].clone();
+                self.eval_unary(&op, &value)
+            }
+            2 => Err(format!("{}: unary operator expected", words[0])),
+            3 if self.is_binary_op(1) || words[1] == "-a" || words[1] == "-o" => {
+                let (left, op, right) = (words[0].clone(), words[1].clone(), words[2].clone());
+                match op.as_str() {
+                    "-a" => Ok(!left.is_empty() && !right.is_empty()),
+                    "-o" => Ok(!left.is_empty() || !right.is_empty()),
+                    _ => self.eval_binary(&left, &op, &right, Kind::Text),
+                }
+            }
+            3 if words[0] == "(" && words[2] == ")" => Ok(!words[1].is_empty()),
+            3 | 4 if words[0] == "!" => {
+                self.words.remove(0);
+                Ok(!self.eval_test()?)
+            }
+            _ => self.eval_all(),
+        }
+    }
+
+    fn eval_or(&mut self) -> Result<bool, String> {
+        let op = if self.is_cond { "||" } else { "-o" };
+        let mut result = self.eval_and()?;
+        while self.peek(0) == Some(op) {
+            self.pos += 1;
+            let skip = self.skip;
+            self.skip = skip || result;
+            let right = self.eval_and()?;
+            self.skip = skip;
+            result = result || right;
+        }
+        Ok(result)
+    }
+
+    fn eval_and(&mut self) -> Result<bool, String> {
+        let op = if self.is_cond { "&&" } else { "-a" };
+        let mut result = self.eval_not()?;
+        while self.peek(0) == Some(op) {
+            self.pos += 1;
+            let skip = self.skip;
+            self.skip = skip || !result;
+            let right = self.eval_not()?;
+            self.skip = skip;
+            result = result && right;
+        }
+        Ok(result)
+    }
+
+    fn eval_not(&mut self) -> Result<bool, String> {
+        if self.peek(0) == Some("!") && self.left() > 1 && !self.is_binary_op(1) {
+            self.pos += 1;
+            return Ok(!self.eval_not()?);
+        }
+        self.eval_primary()
+    }
+
+    fn eval_primary(&mut self) -> Result<bool, String> {
+        if self.peek(0) == Some("(") && !self.is_binary_op(1) {
+            self.pos += 1;
+            let result = self.eval_or()?;
+            if self.peek(0) != Some(")") {
+                return Err(String::from("`)' expected"));
+            }
+            self.pos += 1;
+            return Ok(result);
+        }
+
+        if self.is_binary_op(1) && self.left() > 2 {
+            let left = self.next_word()?;
+            let op = self.next_word()?;
+            let right = self.next_word()?;
+            let kind = match op.as_str() {
+                "=" | "==" | "!=" if self.is_cond => Kind::Pattern,
+                "=~" => Kind::Regex,
+                _ => Kind::Text,
+            };
+            let left = self.expand(&left, Kind::Text);
+            let right = self.expand(&right, kind);
+            return self.eval_binary(&left, &op, &right, kind);
+        }
+
+        let word = self.next_word()?;
+        if UNARY_OPS.contains(&word.as_str()) && self.left() > 0 {
+            let value = self.next_word()?;
+            let value = self.expand(&value, Kind::Text);
+            return self.eval_unary(&word, &value);
+        }
+        Ok(!self.expand(&word, Kind::Text).is_empty())
+    }
+
+    /// Expand the word of `[[ ]]`. Quoted parts of patterns and regexes
+    /// are escaped, so that they match literally.
+    fn expand(&mut self, word: &str, kind: Kind) -> String {
+        if !self.is_cond || self.skip {
+            return word.to_string();
+        }
+        if kind == Kind::Regex {
+            return self.expand_regex(word);
+        }
+        let mut tokens = parsers::parser_line::cmd_to_tokens(word);
+        shell::expand_word(self.sh, &mut tokens);
+        let mut result = String::new();
+        for (sep, token) in tokens {
+            if sep.is_empty() || kind == Kind::Text {
+                result.push_str(&token);
+            } else {
+                result.push_str(&glob::Pattern::escape(&token));
+            }
+        }
+        result
+    }
+
+    /// Expand the regex of `=~`, keeping its special chars like `(`, `|`
+    /// and `\` as they are, e.g. `^ver\ ([0-9]+)\.$minor$`. The quoted
+    /// and escaped chars match literally.
+    fn expand_regex(&mut self, word: &str) -> String {
+        let chars: Vec<char> = word.chars().collect();
+        let len = chars.len();
+        let mut result = String::new();
+        let mut plain = String::new();
+        let mut i = 0;
+        while i < len {
+            let c = chars[i];
+            if c != '\\' && c != '\'' && c != '"' {
+                plain.push(c);
+                i += 1;
+                continue;
+            }
+
+            result.push_str(&shell::extend_env_blindly(self.sh, &plain));
+            plain.clear();
+            if c == '\\' {
+                if i + 1 < len {
+                    result.push_str(&regex::escape(&chars[i + 1].to_string()));
+                }
+                i += 2;
+                continue;
+            }
+            let mut end = i + 1;
+            while end < len && chars[end] != c {
+                if c == '"' && chars[end] == '\\' {
+                    end += 1;
+                }
+                end += 1;
+            }
+            let end = end.min(len - 1);
+            let quoted: String = chars[i..=end].iter().collect();
+            let text = self.expand(&quoted, Kind::Text);
+            result.push_str(&regex::escape(&text));
+            i = end + 1;
+        }
+        result.push_str(&shell::extend_env_blindly(self.sh, &plain));
+        result
+    }
+
+    fn eval_unary(&mut self, op: &str, value: &str) -> Result<bool, String> {
+        if self.skip {
+            return Ok(false);
+        }
+        let result = match op {
+            "-n" => !value.is_empty(),
+            "-z" => value.is_empty(),
+            "-e" => fs::metadata(value).is_ok(),
+            "-f" => fs::metadata(value).map(|x| x.is_file()).unwrap_or(false),
+            "-d" => fs::metadata(value).map(|x| x.is_dir()).unwrap_or(false),
+            "-s" => fs::metadata(value).map(|x| x.len() > 0).unwrap_or(false),
+            "-h" | "-L" => fs::symlink_metadata(value)
+                .map(|x| x.file_type().is_symlink())
+                .unwrap_or(false),
+            _ => {
+                let mode = match op {
+                    "-r" => libc::R_OK,
+                    "-w" => libc::W_OK,
+                    _ => libc::X_OK,
+                };
+                match std::ffi::CString::new(value) {
+                    Ok(x) => unsafe { libc::access(x.as_ptr(), mode) == 0 },
+                    Err(_) => false,
+                }
+            }
+        };
+        Ok(result)
+    }
+
+    fn eval_binary(
+        &mut self,
+        left: &str,
+        op: &str,
+        right: &str,
+        kind: Kind,
+    ) -> Result<bool, String> {
+        if self.skip {
+            return Ok(false);
+        }
+        let result = match op {
+            "=" | "==" if kind == Kind::Pattern => shell::glob_match(right, left),
+            "!=" if kind == Kind::Pattern => !shell::glob_match(right, left),
+            "=" | "==" => left == right,
+            "!=" => left != right,
+            "<" => left < right,
+            ">" => left > right,
+            "=~" => self.match_regex(left, right)?,
+            "-nt" | "-ot" | "-ef" => {
+                let left = fs::metadata(left).ok();
+                let right = fs::metadata(right).ok();
+                match (op, left, right) {
+                    ("-nt", Some(a), Some(b)) => a.mtime() > b.mtime(),
+                    ("-nt", Some(_), None) => true,
+                    ("-ot", Some(a), Some(b)) => a.mtime() < b.mtime(),
+                    ("-ot", None, Some(_)) => true,
+                    ("-ef", Some(a), Some(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+                    _ => false,
+                }
+            }
+            _ => {
+                let a = self.integer_of(left)?;
+                let b = self.integer_of(right)?;
+                match op {
+                    "-eq" => a == b,
+                    "-ne" => a != b,
+                    "-lt" => a < b,
+                    "-le" => a <= b,
+                    "-gt" => a > b,
+                    _ => a >= b,
+                }
+            }
+        };
+        Ok(result)
+    }
+
+    /// Operands of `[[ ]]` are arithmetic expressions, e.g.
+    /// `[[ $n -gt 1 + 2 ]]`, while the ones of `test` are integers.
+    fn integer_of(&mut self, text: &str) -> Result<i64, String> {
+        if self.is_cond {
+            return shell::eval_arith(self.sh, text);
+        }
+        match text.trim().parse::<i64>() {
+            Ok(x) => Ok(x),
+            Err(_) => Err(format!("{}: integer expression expected", text)),
+        }
+    }
+
+    /// `$x =~ regex` keeps the matched text and groups in the array
+    /// `BASH_REMATCH`.
+    fn match_regex(&mut self, text: &str, ptn: &str) -> Result<bool, String> {
+        let re = match Regex::new(ptn) {
+            Ok(x) => x,
+            Err(_) => return Err(format!("{}: invalid regular expression", ptn)),
+        };
+        let mut array = types::Array::Indexed(Default::default());
+        let matched = match re.captures(text) {
+            Some(caps) => {
+                for cap in caps.iter() {
+                    array.push(cap.map_or("", |x| x.as_str()));
+                }
+                true
+            }
+            None => false,
+        };
+        self.sh.envs.remove("BASH_REMATCH");
+        self.sh.set_array("BASH_REMATCH", array);
+        Ok(matched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scripting::{check, run_lines};
+    use crate::shell;
+
+    #[test]
+    fn test_run_test() {
+        let mut sh = shell::Shell::new();
+        let v = vec![
+            ("test -f Cargo.toml", 0),
+            ("test -d Cargo.toml", 1),
+            ("[ -d src -a -e src/main.rs ]", 0),
+            ("[ -s src/main.rs ]", 0),
+            ("[ ! -e foo-not-exist ]", 0),
+            ("[ Cargo.toml -nt foo-not-exist ]", 0),
+            ("[ foo = foo ]", 0),
+            ("[ foo != foo ]", 1),
+            ("[ -n '' -o 2 -ge 10 ]", 1),
+            ("[ '(' -z '' ')' ]", 0),
+            ("[ -n ]", 0),
+            ("test", 1),
+            ("[ a '<' b ]", 0),
+            ("[ a -lt 1 ]", 2),
+            ("[ a = a", 2),
+            ("[[ -f Cargo.toml && ! -d Cargo.toml ]]", 0),
+            ("x=''; [[ -z $x ]]", 0),
+            ("x='a b'; [[ $x == a* ]]", 0),
+            ("x='a b'; [[ $x == \"a*\" ]]", 1),
+            ("x='a b'; [[ $x != *c ]]", 0),
+            ("[[ b > a && ( -e foo-not-exist || 1 -lt 2 ) ]]", 0),
+            ("[[ 1+1 -eq 3 ]]", 1),
+            ("[[ -n $(echo a) ||\n -n '' ]]", 0),
+            ("[[ a =~ [ ]]", 2),
+        ];
+        for (text, status) in v {
+            println!("\ninput: {:?}", text);
+            assert_eq!(run_lines(&mut sh, text), status);
+        }
+
+        let v = [
+            (
+                "x='v1.23'; [[ $x =~ ^v([0-9]+)\\.([0-9]+)$ ]] && S=${BASH_REMATCH[2]}",
+                "23",
+            ),
+            (
+                "re='^(a|b) c$'; [[ 'b c' =~ $re ]] && S=${BASH_REMATCH[1]}",
+                "b",
+            ),
+            ("[[ abc =~ \"a.c\" ]] || S=literal", "literal"),
+            ("[[ a.c =~ ^a\".\"c$ ]] && S=${#BASH_REMATCH[@]}", "1"),
+            ("[[ -n '' && $(S=x) ]] || S=short", "short"),
+            ("if [ -f Cargo.toml ]; then S=file; fi", "file"),
+            ("S=$([[ a == a ]] | cat; echo $?)", "0"),
+        ];
+        check(&mut sh, &v);
+    }
+}
@@ -0,0 +1,184 @@
+use std::io::Write;
+
+use crate::execute;
+use crate::shell;
+use crate::signals;
+use crate::types::Tokens;
+
+/// `trap 'rm -f /tmp/foo.$$' EXIT INT` runs the command when the shell
+/// exits, or gets one of the signals. Besides `EXIT`, there is also the
+/// `ERR` pseudo-signal, i.e. a command failed. `trap - INT` resets the
+/// handler, `trap -l` lists the signals, and `trap` lists the traps.
+pub fn run(sh: &mut shell::Shell, tokens: &Tokens) -> i32 {
+    let args: Vec<&str> = tokens[1..].iter().map(|x| x.1.as_str()).collect();
+    if args.is_empty() || args == ["-p"] {
+        print_traps(sh);
+        return 0;
+    }
+    if args == ["-l"] {
+        list_signals();
+        return 0;
+    }
+
+    let args = if args[0] == "--" {
+        &args[1..]
+    } else {
+        &args[..]
+    };
+    if args.len() < 2 {
+        println_stderr!("cicada: trap: usage: trap [-lp] [[command] signal ...]");
+        return 2;
+    }
+
+    let cmd = args[0];
+    let mut status = 0;
+    for spec in &args[1..] {
+        let name = match trap_name(spec) {
+            Some(x) => x,
+            None => {
+                println_stderr!("cicada: trap: {}: invalid signal specification", spec);
+                status = 1;
+                continue;
+            }
+        };
+        let sig = signals::number(name);
+        if cmd == "-" {
+            sh.traps.remove(name);
+            if let Some(x) = sig {
+                signals::reset(x).ok();
+            }
+            continue;
+        }
+
+        if name == "KILL" || name == "STOP" {
+            println_stderr!("cicada: trap: SIG{}: cannot trap", name);
+            status = 1;
+            continue;
+        }
+        if let Some(x) = sig {
+            // `trap '' INT` makes the shell ignore the signal
+            let result = if cmd.is_empty() {
+                signals::ignore(x)
+            } else {
+                signals::catch(x)
+            };
+            if let Err(e) = result {
+                println_stderr!("cicada: trap: SIG{}: {}", name, e);
+                status = 1;
+                continue;
+            }
+        }
+        sh.traps.insert(name.to_string(), cmd.to_string());
+    }
+    status
+}
+
+/// `EXIT`, `ERR`, or the name of a signal like `INT` for `SIGINT`.
+fn trap_name(spec: &str) -> Option<&'static str> {
+    match spec.to_uppercase().as_str() {
+        "EXIT" | "0" => Some("EXIT"),
+        "ERR" => Some("ERR"),
+        _ => signals::number(spec).and_then(signals::name),
+    }
+}
+
+fn print_traps(sh: &shell::Shell) {
+    let mut names = vec!["EXIT"];
+    names.extend(signals::SIGNALS.iter().map(|x| x.0));
+    names.push("ERR");
+    for name in names {
+        if let Some(cmd) = sh.traps.get(name) {
+            let name = if signals::number(name).is_some() {
+                format!("SIG{}", name)
+            } else {
+                name.to_string()
+            };
+            println!("trap -- '{}' {}", cmd.replace('\'', "'\\''"), name);
+        }
+    }
+}
+
+fn list_signals() {
+    let items: Vec<String> = signals::SIGNALS
+        .iter()
+        .map(|x| format!("{:>2}) SIG{}", x.1, x.0))
+        .collect();
+    for line in items.chunks(5) {
+        println!("{}", line.join("\t"));
+    }
+}
+
+/// Run the traps of the signals caught, e.g. `trap 'echo bye' INT`.
+pub fn run_pending(sh: &mut shell::Shell) {
+    for sig in signals::take_pending() {
+        if let Some(name) = signals::name(sig) {
+            run_trap(sh, name);
+        }
+    }
+}
+
+/// Run the `ERR` trap, when a command failed.
+pub fn run_err(sh: &mut shell::Shell) {
+    run_trap(sh, "ERR");
+}
+
+/// Run the `EXIT` trap, when the shell is going to exit. It runs only
+/// once, even if `exit` is called in it.
+pub fn run_exit(sh: &mut shell::Shell) {
+    run_trap(sh, "EXIT");
+}
+
+fn run_trap(sh: &mut shell::Shell, name: &str) {
+    let cmd = match sh.traps.get(name) {
+        Some(x) if !x.is_empty() => x.clone(),
+        _ => return,
+    };
+    if name == "EXIT" {
+        sh.traps.remove(name);
+    }
+    // `$?` is kept after traps, and the failures in them do not trigger
+    // the `ERR` trap or `set -e`
+    let status = sh.previous_status;
+    sh.cond_depth += 1;
+    execute::run_procs(sh, &cmd, false);
+    sh.cond_depth -= 1;
+    sh.previous_status = status;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scripting::{check, run_lines};
+    use crate::shell;
+
+    #[test]
+    fn test_run_trap() {
+        let mut sh = shell::Shell::new();
+        let v = [
+            (
+                "trap 'S=$S-$?' ERR; false; true; (exit 3); trap - ERR; false",
+                "-1-3",
+            ),
+            (
+                "f() { false; }; trap 'S=$S-f' ERR; f; f || true; trap - ERR",
+                "-f",
+            ),
+            ("S=$(trap 'echo bye' EXIT; echo hi)", "hi\nbye"),
+            ("S=$(trap 'echo bye; exit 2' EXIT; exit 3; echo no)", "bye"),
+            (
+                "S=$(trap 'echo usr1' USR1; kill -USR1 $$; echo next)",
+                "usr1\nnext",
+            ),
+            ("S=$(trap 'false' SIGUSR2; kill -12 $$; echo $?)", "0"),
+            ("S=$(trap 'echo no' EXIT; trap - EXIT)", ""),
+        ];
+        check(&mut sh, &v);
+
+        assert_eq!(run_lines(&mut sh, "trap 'echo' EXIT FOO"), 1);
+        assert_eq!(sh.traps.get("EXIT"), Some(&String::from("echo")));
+        assert_eq!(run_lines(&mut sh, "trap - exit"), 0);
+        assert!(sh.traps.is_empty());
+        assert_eq!(run_lines(&mut sh, "trap 'echo' KILL"), 1);
+        assert_eq!(run_lines(&mut sh, "trap 'echo' SIGSTOP"), 1);
+        assert!(sh.traps.is_empty());
+    }
+}
@@ -60,7 +60,7 @@ fn list_envs() -> i32 {
     0
 }
 
-fn enter_env(sh: &shell::Shell, path: &str) -> i32 {
+fn enter_env(sh: &mut shell::Shell, path: &str) -> i32 {
     if in_env() {
         println_stderr!("vox: already in env");
         return 1;
@@ -81,7 +81,7 @@ fn enter_env(sh: &shell::Shell, path: &str) -> i32 {
     0
 }
 
-fn exit_env(sh: &shell::Shell) -> i32 {
+fn exit_env(sh: &mut shell::Shell) -> i32 {
     if !in_env() {
         println_stderr!("vox: not in an env");
         return 0;
@@ -111,7 +111,7 @@ fn exit_env(sh: &shell::Shell) -> i32 {
     0
 }
 
-pub fn run(sh: &shell::Shell, tokens: &types::Tokens) -> i32 {
+pub fn run(sh: &mut shell::Shell, tokens: &types::Tokens) -> i32 {
     let args = parsers::parser_line::tokens_to_args(tokens);
     let len = args.len();
     if len == 1 {
@@ -5,6 +5,7 @@ use std::fs::File;
 use std::io::{self, Read, Write};
 use std::os::unix::io::FromRawFd;
 use std::os::unix::io::RawFd;
+use std::path::Path;
 use std::process;
 
 use libc;
@@ -21,6 +22,7 @@ use crate::builtins;
 use crate::jobc;
 use crate::libs;
 use crate::parsers;
+use crate::scripting;
 use crate::shell;
 use crate::tools::{self, clog};
 use crate::types;
@@ -52,10 +54,14 @@ fn tokens_to_cmd_tokens(tokens: &Tokens) -> Vec<Tokens> {
     for token in tokens {
         let sep = &token.0;
         let value = &token.1;
-        if sep.is_empty() && value == "|" {
+        if sep.is_empty() && (value == "|" || value == "|&") {
             if cmd.is_empty() {
                 return Vec::new();
             }
+            if value == "|&" {
+                // `a |& b` is the short form of `a 2>&1 | b`
+                cmd.push((String::new(), String::from("2>&1")));
+            }
             cmds.push(cmd.clone());
             cmd = Vec::new();
         } else {
@@ -70,61 +76,25 @@ fn tokens_to_cmd_tokens(tokens: &Tokens) -> Vec<Tokens> {
 }
 
 pub fn run_procs(sh: &mut shell::Shell, line: &str, tty: bool) -> i32 {
-    if tools::is_arithmetic(line) {
-        if line.contains('.') {
-            match run_calc_float(line) {
-                Ok(x) => {
-                    println!("{}", x);
-                    return 0;
-                }
-                Err(e) => {
-                    println!("Error: {}", e);
-                    return 1;
-                }
-            }
-        } else {
-            match run_calc_int(line) {
-                Ok(x) => {
-                    println!("{}", x);
-                    return 0;
-                }
-                Err(e) => {
-                    println!("Error: {}", e);
-                    return 1;
-                }
-            }
-        }
-    }
-
-    let mut status = 0;
-    let mut sep = String::new();
-    for token in parsers::parser_line::line_to_cmds(&line) {
-        if token == ";" || token == "&&" || token == "||" {
-            sep = token.clone();
-            continue;
-        }
-        if sep == "&&" && status != 0 {
-            break;
-        }
-        if sep == "||" && status == 0 {
-            break;
+    match parsers::parser_script::parse_script(line) {
+        Ok(stmts) => scripting::run_stmts(sh, &stmts, tty),
+        Err(e) => {
+            println_stderr!("cicada: {}", e);
+            2
         }
-        let cmd = token.clone();
-        status = run_proc(sh, &cmd, tty);
     }
-    status
 }
 
 fn drain_env_tokens(tokens: &mut Tokens) -> HashMap<String, String> {
     let mut envs: HashMap<String, String> = HashMap::new();
     let mut n = 0;
     for (sep, text) in tokens.iter() {
-        if !sep.is_empty() || !tools::re_contains(text, r"^([a-zA-Z0-9_]+)=(.*)$") {
+        if !sep.is_empty() || !tools::re_contains(text, r"(?s)^([a-zA-Z0-9_]+)=(.*)$") {
             break;
         }
 
         let re;
-        match Regex::new(r"^([a-zA-Z0-9_]+)=(.*)$") {
+        match Regex::new(r"(?s)^([a-zA-Z0-9_]+)=(.*)$") {
             Ok(x) => {
                 re = x;
             }
@@ -149,7 +119,30 @@ fn drain_env_tokens(tokens: &mut Tokens) -> HashMap<String, String> {
 
 fn line_to_tokens(sh: &mut shell::Shell, line: &str) -> (Tokens, HashMap<String, String>) {
     let mut tokens = parsers::parser_line::cmd_to_tokens(line);
+    sh.expand_failed = false;
+    sh.subst_status = None;
+
+    // assignments like `arr=(a b c)`, `arr[1]=x` and `foo+=bar`
+    let is_assignment = |x: &(String, String)| x.0.is_empty() && shell::is_assignment(&x.1);
+    let is_array = |x: &(String, String)| x.0.is_empty() && shell::is_array_assignment(&x.1);
+    if tokens.iter().all(is_assignment) && tokens.iter().any(is_array) {
+        for (_, text) in tokens.iter() {
+            if let Err(e) = shell::run_assignment(sh, text) {
+                println_stderr!("cicada: {}", e);
+                sh.expand_failed = true;
+                break;
+            }
+        }
+        return (Vec::new(), HashMap::new());
+    }
+
     shell::do_expansion(sh, &mut tokens);
+    if sh.expand_failed {
+        return (Vec::new(), HashMap::new());
+    }
+    if sh.options.xtrace {
+        print_trace(&tokens);
+    }
     let envs = drain_env_tokens(&mut tokens);
 
     if tokens.is_empty() {
@@ -161,25 +154,63 @@ fn line_to_tokens(sh: &mut shell::Shell, line: &str) -> (Tokens, HashMap<String,
     return (tokens, envs);
 }
 
+/// For `set -x`, print the expanded command to stderr, quoting the words
+/// when needed, e.g. `+ echo 'a b'`.
+fn print_trace(tokens: &Tokens) {
+    let mut words = Vec::new();
+    for (sep, token) in tokens {
+        let plain = token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=+/.,:@%^".contains(c));
+        if sep == "(" {
+            words.push(format!("({})", token));
+        } else if sep.is_empty() || (!token.is_empty() && plain) {
+            words.push(token.clone());
+        } else {
+            words.push(format!("'{}'", token.replace('\'', "'\\''")));
+        }
+    }
+    println_stderr!("+ {}", words.join(" "));
+}
+
 pub fn run_proc(sh: &mut shell::Shell, line: &str, tty: bool) -> i32 {
     let (mut tokens, envs) = line_to_tokens(sh, line);
     if tokens.is_empty() {
-        return 0;
+        // e.g. `x=$(false)` fails
+        return if sh.expand_failed {
+            1
+        } else {
+            sh.subst_status.unwrap_or(0)
+        };
     }
 
-    let cmd = tokens[0].1.clone();
+    // `(...)` runs in a sub-process, see `run_command()`
+    let cmd = if tokens[0].0 == "(" {
+        String::new()
+    } else {
+        tokens[0].1.clone()
+    };
     // for built-ins
     if cmd == "bg" {
         return builtins::bg::run(sh, &tokens);
     }
+    if cmd == "break" {
+        return builtins::loopctl::run_break(sh, &tokens);
+    }
     if cmd == "cd" {
         return builtins::cd::run(sh, &tokens);
     }
+    if cmd == "continue" {
+        return builtins::loopctl::run_continue(sh, &tokens);
+    }
+    if cmd == "eval" {
+        return builtins::eval::run(sh, &tokens, tty);
+    }
     if cmd == "export" {
         return builtins::export::run(sh, &tokens);
     }
     if cmd == "exec" {
-        return builtins::exec::run(&tokens);
+        return builtins::exec::run(sh, &tokens);
     }
     if cmd == "exit" {
         return builtins::exit::run(sh, &tokens);
@@ -187,48 +218,65 @@ pub fn run_proc(sh: &mut shell::Shell, line: &str, tty: bool) -> i32 {
     if cmd == "fg" {
         return builtins::fg::run(sh, &tokens);
     }
+    if cmd == "local" {
+        return builtins::local::run(sh, &tokens);
+    }
+    if cmd == "return" {
+        return builtins::ret::run(sh, &tokens);
+    }
+    if cmd == "shift" {
+        return builtins::shift::run(sh, &tokens);
+    }
+    if cmd == "source" || cmd == "." {
+        return builtins::source::run(sh, &tokens);
+    }
     if cmd == "vox" && tokens.len() > 1 && (tokens[1].1 == "enter" || tokens[1].1 == "exit") {
         return builtins::vox::run(sh, &tokens);
     }
 
     // for any other situations
     let mut background = false;
-    let mut len = tokens.len();
+    let len = tokens.len();
     if len > 1 && tokens[len - 1].1 == "&" {
         background = true;
         tokens.pop();
-        len -= 1;
-    }
-    let mut redirect_from = String::new();
-    let has_redirect_from = tokens.iter().any(|x| x.1 == "<");
-    if has_redirect_from {
-        if let Some(idx) = tokens.iter().position(|x| x.1 == "<") {
-            tokens.remove(idx);
-            len -= 1;
-            if len > idx {
-                redirect_from = tokens.remove(idx).1;
-                len -= 1;
-            } else {
-                println!("cicada: invalid command: cannot get redirect from");
-                return 1;
-            }
-        }
     }
-    if len == 0 {
-        return 0;
+
+    // `alias`, `declare`, `set`, `trap` and `test` in pipelines or with
+    // redirections run in child processes, see `run_command()`. Functions,
+    // `echo`, `printf` and `read` run in the shell process even with
+    // redirections, e.g. `f > log` or `read x < foo`, so that the variables
+    // they set are kept, see `run_redirected()`.
+    let is_pipeline = tokens.iter().any(|x| {
+        x.0.is_empty() && (x.1.starts_with('|') || x.1.contains('>') || x.1.contains('<'))
+    });
+    let has_pipe = tokens
+        .iter()
+        .any(|x| x.0.is_empty() && x.1.starts_with('|'));
+    if sh.funcs.contains_key(&cmd) && !background && !has_pipe {
+        return run_redirected(sh, &tokens, &envs, tty);
+    }
+    if cmd == "alias" && !background && !is_pipeline {
+        return builtins::alias::run(sh, &tokens);
+    }
+    if cmd == "declare" && !background && !is_pipeline {
+        return builtins::declare::run(sh, &tokens);
+    }
+    if cmd == "set" && !background && !is_pipeline {
+        return builtins::set::run(sh, &tokens);
+    }
+    if cmd == "trap" && !background && !is_pipeline {
+        return builtins::trap::run(sh, &tokens);
+    }
+    if (cmd == "test" || cmd == "[") && !background && !is_pipeline {
+        return builtins::test::run(sh, &tokens);
+    }
+    if (cmd == "echo" || cmd == "printf" || cmd == "read") && !background && !has_pipe {
+        return run_redirected(sh, &tokens, &envs, tty);
     }
 
     let log_cmd = !sh.cmd.starts_with(' ');
-    let (term_given, cr) = run_pipeline(
-        sh,
-        &tokens,
-        &redirect_from,
-        background,
-        tty,
-        false,
-        log_cmd,
-        Some(envs),
-    );
+    let (term_given, cr) = run_pipeline(sh, &tokens, background, tty, false, log_cmd, Some(envs));
 
     if term_given {
         unsafe {
@@ -240,9 +288,283 @@ pub fn run_proc(sh: &mut shell::Shell, line: &str, tty: bool) -> i32 {
     cr.status
 }
 
+/// Run functions and builtins like `echo` in the shell process, with the
+/// redirections applied to the shell until they finish.
+fn run_redirected(
+    sh: &mut shell::Shell,
+    tokens: &Tokens,
+    envs: &HashMap<String, String>,
+    tty: bool,
+) -> i32 {
+    let cmd = match parsers::parser_line::cmd_to_with_redirects(tokens) {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: {}", e);
+            return 1;
+        }
+    };
+    let saved = match apply_redirects(&cmd.redirects, sh.options.noclobber) {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: {}", e);
+            return 1;
+        }
+    };
+    let status = match cmd.tokens[0].1.as_str() {
+        x if sh.funcs.contains_key(x) => scripting::run_func(sh, &cmd.tokens, tty),
+        "echo" => builtins::echo::run(&cmd.tokens),
+        "printf" => builtins::printf::run(sh, &cmd.tokens),
+        _ => builtins::read::run(sh, &cmd.tokens, envs),
+    };
+    restore_fds(saved);
+    status
+}
+
+/// Run the command of process substitution `<(cmd)` (or `>(cmd)` when
+/// `is_input` is false) in a child process. Returns the fd of the pipe
+/// end kept in the shell, which is closed by `jobc::wait_proc_subs()`.
+pub fn run_proc_substitution(
+    sh: &mut shell::Shell,
+    line: &str,
+    is_input: bool,
+) -> Result<RawFd, String> {
+    let (fd_read, fd_write) = match pipe() {
+        Ok(x) => x,
+        Err(e) => return Err(format!("pipe error: {:?}", e)),
+    };
+    let (fd_keep, fd_child) = if is_input {
+        (fd_read, fd_write)
+    } else {
+        (fd_write, fd_read)
+    };
+    // keep it away from fds used in redirections like `3>foo`
+    let fd = unsafe { libc::fcntl(fd_keep, libc::F_DUPFD, 60) };
+    unsafe {
+        libc::close(fd_keep);
+    }
+
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+    match fork() {
+        Ok(ForkResult::Child) => {
+            unsafe {
+                libc::close(fd);
+                for (fd_sub, _) in sh.proc_subs.iter() {
+                    libc::close(*fd_sub);
+                }
+                libc::dup2(fd_child, if is_input { 1 } else { 0 });
+                libc::close(fd_child);
+            }
+            sh.proc_subs.clear();
+            let status = run_procs(sh, line, false);
+            process::exit(status);
+        }
+        Ok(ForkResult::Parent { child, .. }) => {
+            unsafe {
+                libc::close(fd_child);
+            }
+            sh.proc_subs.push((fd, child.into()));
+            Ok(fd)
+        }
+        Err(e) => {
+            unsafe {
+                libc::close(fd);
+                libc::close(fd_child);
+            }
+            Err(format!("fork error: {:?}", e))
+        }
+    }
+}
+
+/// Run the command of command substitution `$(cmd)` in a child process,
+/// and returns its output without the trailing newlines.
+pub fn run_command_substitution(sh: &mut shell::Shell, line: &str) -> String {
+    let (fd_read, fd_write) = match pipe() {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: pipe error: {:?}", e);
+            return String::new();
+        }
+    };
+
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+    match fork() {
+        Ok(ForkResult::Child) => {
+            unsafe {
+                libc::close(fd_read);
+                libc::dup2(fd_write, 1);
+                libc::close(fd_write);
+            }
+            // traps of the shell are not for sub-shells
+            sh.traps.clear();
+            let status = run_procs(sh, line, false);
+            builtins::trap::run_exit(sh);
+            io::stdout().flush().ok();
+            process::exit(status);
+        }
+        Ok(ForkResult::Parent { child, .. }) => {
+            unsafe {
+                libc::close(fd_write);
+            }
+            let mut output = Vec::new();
+            let mut f = unsafe { File::from_raw_fd(fd_read) };
+            if let Err(e) = f.read_to_end(&mut output) {
+                println_stderr!("cicada: read error: {:?}", e);
+            }
+            let pid = child.into();
+            sh.previous_status = jobc::wait_process(sh, pid, pid, true);
+            sh.subst_status = Some(sh.previous_status);
+            let output = String::from_utf8_lossy(&output);
+            output.trim_end_matches('\n').to_string()
+        }
+        Err(e) => {
+            unsafe {
+                libc::close(fd_read);
+                libc::close(fd_write);
+            }
+            println_stderr!("cicada: fork error: {:?}", e);
+            String::new()
+        }
+    }
+}
+
+/// Apply a redirection like `("2", ">", "&1")` in current process. With
+/// `noclobber` (i.e. `set -C`), `>` cannot overwrite files, use `>|`.
+fn apply_redirect(redirect: &types::Redirection, noclobber: bool) -> Result<(), String> {
+    let (from_, op_, to_) = redirect;
+    let fd: RawFd = match from_.parse() {
+        Ok(x) => x,
+        Err(_) => return Err(format!("{}: bad file descriptor", from_)),
+    };
+
+    let fd_new = if op_ == "<<" || op_ == "<<<" {
+        tools::create_raw_fd_from_text(to_)?
+    } else if to_ == "&-" {
+        unsafe {
+            libc::close(fd);
+        }
+        return Ok(());
+    } else if let Some(fd_to) = to_.strip_prefix('&') {
+        if unsafe { libc::dup2(fd_to.parse().unwrap_or(-1), fd) } == -1 {
+            return Err(format!("{}: bad file descriptor", fd_to));
+        }
+        return Ok(());
+    } else {
+        if noclobber && op_ == ">" && Path::new(to_).is_file() {
+            return Err(format!("{}: cannot overwrite existing file", to_));
+        }
+        tools::create_raw_fd_from_file(to_, op_)?
+    };
+
+    if fd_new != fd {
+        unsafe {
+            libc::dup2(fd_new, fd);
+            libc::close(fd_new);
+        }
+    }
+    Ok(())
+}
+
+/// Apply redirections in the shell process itself, e.g. for compound
+/// commands like `{ foo; bar; } > foo.log`. Returns the fds saved for
+/// `restore_fds()`.
+pub fn apply_redirects(
+    redirects: &[types::Redirection],
+    noclobber: bool,
+) -> Result<Vec<(RawFd, RawFd)>, String> {
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+    let mut saved: Vec<(RawFd, RawFd)> = Vec::new();
+    for redirect in redirects {
+        if let Ok(fd) = redirect.0.parse::<RawFd>() {
+            if !saved.iter().any(|x| x.0 == fd) {
+                // keep saved fds out of the way of redirections like `3>foo`
+                let fd_saved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) };
+                saved.push((fd, fd_saved));
+            }
+        }
+        if let Err(e) = apply_redirect(redirect, noclobber) {
+            restore_fds(saved);
+            return Err(e);
+        }
+    }
+    Ok(saved)
+}
+
+/// Restore fds changed by `apply_redirects()`.
+pub fn restore_fds(saved: Vec<(RawFd, RawFd)>) {
+    io::stdout().flush().ok();
+    io::stderr().flush().ok();
+    for (fd, fd_saved) in saved.into_iter().rev() {
+        unsafe {
+            if fd_saved == -1 {
+                libc::close(fd);
+            } else {
+                libc::dup2(fd_saved, fd);
+                libc::close(fd_saved);
+            }
+        }
+    }
+}
+
+/// Run calculator lines typed at the prompt, see `tools::is_arithmetic()`.
+pub fn run_calc(sh: &mut shell::Shell, line: &str) -> i32 {
+    match run_calculator(sh, line) {
+        Ok(x) => {
+            println!("{}", x);
+            0
+        }
+        Err(e) => {
+            println!("Error: {}", e);
+            1
+        }
+    }
+}
+
+/// Run calculator lines like `(1 + 2) * 3`, `sqrt(2) / 2` or `255 in hex`,
+/// `ans` in them is the result of the previous line.
+fn run_calculator(sh: &mut shell::Shell, line: &str) -> Result<String, String> {
+    let mut line = line.trim().to_string();
+    let mut base = String::from("dec");
+    let ptn_base = r"^(.*?)\s+in\s+(hex|oct|bin|dec)$";
+    if let Some(x) = libs::re::find_first_group(ptn_base, &line) {
+        base = line[line.len() - 3..].to_string();
+        line = x;
+    }
+    if let Ok(re) = Regex::new(r"\bans\b") {
+        let ans = format!("({})", sh.calc_ans);
+        line = re.replace_all(&line, ans.as_str()).to_string();
+    }
+
+    // functions like `sqrt(2)` work on floats only
+    if line.contains('.') || tools::re_contains(&line, r"[a-z]\s*\(") {
+        if base != "dec" {
+            return Err(format!("cannot show floats in {}", base));
+        }
+        sh.calc_ans = run_calc_float(&line)?.to_string();
+        return Ok(sh.calc_ans.clone());
+    }
+
+    let x = run_calc_int(&line)?;
+    sh.calc_ans = x.to_string();
+    let sign = if x < 0 { "-" } else { "" };
+    let result = match base.as_str() {
+        "hex" => format!("{}0x{:x}", sign, x.unsigned_abs()),
+        "oct" => format!("{}0o{:o}", sign, x.unsigned_abs()),
+        "bin" => format!("{}0b{:b}", sign, x.unsigned_abs()),
+        _ => x.to_string(),
+    };
+    Ok(result)
+}
+
 fn run_calc_float(line: &str) -> Result<f64, String> {
     match parsers::parser_float::expr_float(line.as_bytes()) {
-        IResult::Done(_, x) => Ok(x),
+        IResult::Done(&[], x) => x,
+        IResult::Done(rest, _) => Err(format!(
+            "syntax error near: {}",
+            String::from_utf8_lossy(rest)
+        )),
         IResult::Error(e) => Err(e.description().to_owned()),
         IResult::Incomplete(_) => Err(String::from("Incomplete arithmetic")),
     }
@@ -250,7 +572,11 @@ fn run_calc_float(line: &str) -> Result<f64, String> {
 
 fn run_calc_int(line: &str) -> Result<i64, String> {
     match parsers::parser_int::expr_int(line.as_bytes()) {
-        IResult::Done(_, x) => Ok(x),
+        IResult::Done(&[], x) => x,
+        IResult::Done(rest, _) => Err(format!(
+            "syntax error near: {}",
+            String::from_utf8_lossy(rest)
+        )),
         IResult::Error(e) => Err(e.description().to_owned()),
         IResult::Incomplete(_) => Err(String::from("Incomplete arithmetic")),
     }
@@ -336,97 +662,79 @@ fn run_command(
                 let fds = pipes[idx_cmd];
                 unsafe {
                     libc::dup2(fds.1, 1);
-                    // libc::close(fds.1);
                 }
             }
 
-            if idx_cmd == 0 && !options.redirect_from.is_empty() {
-                let fd = tools::get_fd_from_file(&options.redirect_from);
+            // do not leak fds of pipes into the command, or redirections
+            // like `>&3` would work with them. Pipes before this one are
+            // closed already.
+            for fds in pipes.iter().skip(idx_cmd) {
                 unsafe {
-                    libc::dup2(fd, 0);
-                    libc::close(fd);
+                    libc::close(fds.0);
+                    libc::close(fds.1);
                 }
             }
 
-            let mut stdout_redirected = false;
-            let mut stderr_redirected = false;
-            for item in &cmd.redirects {
-                let from_ = &item.0;
-                let op_ = &item.1;
-                let to_ = &item.2;
-                if to_ == "&1" && from_ == "2" {
-                    unsafe {
-                        if idx_cmd < pipes_count {
-                            let fds = pipes[idx_cmd];
-                            libc::dup2(fds.1, 2);
-                        // libc::close(fds.1);
-                        } else if !options.capture_output {
-                            let fd = libc::dup(1);
-                            libc::dup2(fd, 2);
-                        } else {
-                            // note: capture output with redirections does not
-                            // make much sense
-                        }
-                    }
-                } else if to_ == "&2" && from_ == "1" {
-                    unsafe {
-                        if idx_cmd < pipes_count || !options.capture_output {
-                            let fd = libc::dup(2);
-                            libc::dup2(fd, 1);
-                        } else {
-                            // note: capture output with redirections does not
-                            // make much sense
-                        }
-                    }
-                } else {
-                    let append = op_ == ">>";
-                    match tools::create_raw_fd_from_file(to_, append) {
-                        Ok(fd) => {
-                            if from_ == "1" {
-                                unsafe {
-                                    libc::dup2(fd, 1);
-                                }
-                                stdout_redirected = true;
-                            } else {
-                                unsafe {
-                                    libc::dup2(fd, 2);
-                                }
-                                stderr_redirected = true;
-                            }
-                        }
-                        Err(e) => {
-                            println_stderr!("cicada: {}", e);
-                            *cmd_result = CommandResult::error();
-                            return 0;
-                        }
-                    }
+            // capture output of last process if needed.
+            unsafe {
+                libc::close(fds_capture_stdout.0);
+                libc::close(fds_capture_stderr.0);
+                if idx_cmd == pipes_count && options.capture_output {
+                    libc::dup2(fds_capture_stdout.1, 1);
+                    libc::dup2(fds_capture_stderr.1, 2);
                 }
+                libc::close(fds_capture_stdout.1);
+                libc::close(fds_capture_stderr.1);
             }
 
-            // capture output of last process if needed.
-            if idx_cmd == pipes_count && options.capture_output {
-                unsafe {
-                    if !stdout_redirected {
-                        libc::close(fds_capture_stdout.0);
-                        libc::dup2(fds_capture_stdout.1, 1);
-                        libc::close(fds_capture_stdout.1);
-                    }
-
-                    if !stderr_redirected {
-                        libc::close(fds_capture_stderr.0);
-                        libc::dup2(fds_capture_stderr.1, 2);
-                        libc::close(fds_capture_stderr.1);
-                    }
+            // redirections are applied from left to right, so that
+            // `2>&1 > foo.log` is not the same as `> foo.log 2>&1`
+            for item in &cmd.redirects {
+                if let Err(e) = apply_redirect(item, sh.options.noclobber) {
+                    println_stderr!("cicada: {}", e);
+                    process::exit(1);
                 }
             }
 
             let program = &cmd.tokens[0].1;
-            if program == "history" {
+            if cmd.tokens[0].0 == "(" {
+                sh.traps.clear();
+                let status = run_procs(sh, program, options.isatty);
+                builtins::trap::run_exit(sh);
+                process::exit(status);
+            } else if sh.funcs.contains_key(program) {
+                let status = scripting::run_func(sh, &cmd.tokens, false);
+                process::exit(status);
+            } else if program == "history" {
                 let status = builtins::history::run(&cmd);
                 process::exit(status);
             } else if program == "vox" {
                 let status = builtins::vox::run(sh, &cmd.tokens);
                 process::exit(status);
+            } else if program == "alias" {
+                let status = builtins::alias::run(sh, &cmd.tokens);
+                process::exit(status);
+            } else if program == "declare" {
+                let status = builtins::declare::run(sh, &cmd.tokens);
+                process::exit(status);
+            } else if program == "echo" {
+                let status = builtins::echo::run(&cmd.tokens);
+                process::exit(status);
+            } else if program == "printf" {
+                let status = builtins::printf::run(sh, &cmd.tokens);
+                process::exit(status);
+            } else if program == "read" {
+                let status = builtins::read::run(sh, &cmd.tokens, &options.envs);
+                process::exit(status);
+            } else if program == "set" {
+                let status = builtins::set::run(sh, &cmd.tokens);
+                process::exit(status);
+            } else if program == "trap" {
+                let status = builtins::trap::run(sh, &cmd.tokens);
+                process::exit(status);
+            } else if program == "test" || program == "[" {
+                let status = builtins::test::run(sh, &cmd.tokens);
+                process::exit(status);
             } else if program == "cinfo" {
                 let status = builtins::cinfo::run();
                 process::exit(status);
@@ -437,8 +745,14 @@ fn run_command(
 
             // We are certain that our string doesn't have 0 bytes in the
             // middle, so we can use CString::new().expect()
+            // exported variables are shadowed by the locals of the function
+            // calls, and by the assignments before the command
             let mut c_envs: Vec<_> = env::vars()
-                .map(|(k, v)| CString::new(format!("{}={}", k, v).as_str()).expect("CString error"))
+                .filter(|(k, _)| !options.envs.contains_key(k))
+                .map(|(k, v)| {
+                    let v = sh.get_local_env(&k).unwrap_or(v);
+                    CString::new(format!("{}={}", k, v).as_str()).expect("CString error")
+                })
                 .collect();
             for (key, value) in options.envs.iter() {
                 c_envs.push(
@@ -513,13 +827,18 @@ fn run_command(
                     libc::close(fds.1);
                 }
             }
-
-            if idx_cmd == pipes_count && options.capture_output {
+            if idx_cmd > 0 {
+                let fds_prev = pipes[idx_cmd - 1];
                 unsafe {
-                    libc::close(fds_capture_stdout.1);
-                    libc::close(fds_capture_stderr.1);
+                    libc::close(fds_prev.0);
                 }
+            }
 
+            unsafe {
+                libc::close(fds_capture_stdout.1);
+                libc::close(fds_capture_stderr.1);
+            }
+            if idx_cmd == pipes_count && options.capture_output {
                 let mut f_out = unsafe { File::from_raw_fd(fds_capture_stdout.0) };
                 let mut s_out = String::new();
                 f_out.read_to_string(&mut s_out).expect("fds stdout");
@@ -533,6 +852,11 @@ fn run_command(
                     stdout: s_out.clone(),
                     stderr: s_err.clone(),
                 }
+            } else {
+                unsafe {
+                    libc::close(fds_capture_stdout.0);
+                    libc::close(fds_capture_stderr.0);
+                }
             }
 
             return pid;
@@ -549,7 +873,6 @@ fn run_command(
 pub fn run_pipeline(
     sh: &mut shell::Shell,
     tokens: &Tokens,
-    redirect_from: &str,
     background: bool,
     tty: bool,
     capture_output: bool,
@@ -625,7 +948,6 @@ pub fn run_pipeline(
         }
 
         let options = CommandOptions {
-            redirect_from: redirect_from.to_string(),
             isatty: isatty,
             capture_output: capture_output,
             background: background,
@@ -656,8 +978,16 @@ pub fn run_pipeline(
         }
     }
 
+    // with `set -o pipefail`, status of the pipeline is the one of the
+    // last command that failed
+    let mut failed_status = 0;
     for pid in &children {
-        let status = jobc::wait_process(sh, pgid, *pid, true);
+        let mut status = jobc::wait_process(sh, pgid, *pid, true);
+        if sh.options.pipefail && status != 0 {
+            failed_status = status;
+        } else if sh.options.pipefail {
+            status = failed_status;
+        }
         if capture_output {
             cmd_result.status = status;
         } else {
@@ -673,45 +1003,20 @@ pub fn run_pipeline(
 }
 
 fn run_with_shell<'a, 'b>(sh: &'a mut shell::Shell, line: &'b str) -> CommandResult {
+    let proc_subs_num = sh.proc_subs.len();
     let (mut tokens, envs) = line_to_tokens(sh, &line);
     if tokens.is_empty() {
+        jobc::wait_proc_subs(sh, proc_subs_num);
         return CommandResult::new();
     }
 
-    let mut len = tokens.len();
+    let len = tokens.len();
     if len > 1 && tokens[len - 1].1 == "&" {
         tokens.pop();
-        len -= 1;
-    }
-    let mut redirect_from = String::new();
-    let has_redirect_from = tokens.iter().any(|x| x.1 == "<");
-    if has_redirect_from {
-        if let Some(idx) = tokens.iter().position(|x| x.1 == "<") {
-            tokens.remove(idx);
-            len -= 1;
-            if len > idx {
-                redirect_from = tokens.remove(idx).1;
-                len -= 1;
-            } else {
-                println_stderr!("cicada: invalid command: cannot get redirect from");
-                return CommandResult::error();
-            }
-        }
-    }
-    if len == 0 {
-        return CommandResult::new();
     }
 
-    let (_, cmd_result) = run_pipeline(
-        sh,
-        &tokens,
-        redirect_from.as_str(),
-        false,
-        false,
-        true,
-        false,
-        Some(envs),
-    );
+    let (_, cmd_result) = run_pipeline(sh, &tokens, false, false, true, false, Some(envs));
+    jobc::wait_proc_subs(sh, proc_subs_num);
     cmd_result
 }
 
@@ -724,6 +1029,8 @@ pub fn run(line: &str) -> CommandResult {
 mod tests {
     use super::run_calc_float;
     use super::run_calc_int;
+    use super::run_calculator;
+    use super::run_procs;
     use super::run_with_shell;
     use super::shell;
     use super::tools;
@@ -739,6 +1046,35 @@ mod tests {
     #[test]
     fn test_run_calc_int() {
         assert_eq!(run_calc_int("(5 + 2 * 3 - 4) / 3"), Ok(2));
+        assert!(run_calc_int("1 / (2 - 2)").is_err());
+        assert!(run_calc_int("1 + 2)").is_err());
+    }
+
+    #[test]
+    fn test_run_calculator() {
+        let mut sh = shell::Shell::new();
+        let v = vec![
+            ("255 in hex", "0xff"),
+            ("ans + 1", "256"),
+            ("-5 in bin", "-0b101"),
+            ("0o17 in oct", "0o17"),
+            ("sqrt(ans * ans)", "15"),
+            ("ans / 2.0", "7.5"),
+        ];
+        for (line, expected) in v {
+            assert_eq!(run_calculator(&mut sh, line), Ok(expected.to_string()));
+        }
+        assert!(run_calculator(&mut sh, "1.5 in hex").is_err());
+        assert!(run_calculator(&mut sh, "1 % 0").is_err());
+        assert_eq!(sh.calc_ans, "7.5");
+    }
+
+    #[test]
+    fn test_run_signaled() {
+        // commands killed by signal N have status 128 + N
+        let mut sh = shell::Shell::new();
+        assert_eq!(run_procs(&mut sh, "sh -c 'kill -TERM $$'", false), 143);
+        assert_eq!(run_procs(&mut sh, "sh -c 'kill -KILL $$'", false), 137);
     }
 
     #[test]
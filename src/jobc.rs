@@ -77,7 +77,7 @@ pub fn wait_process(sh: &mut shell::Shell, gid: i32, pid: i32, stop: bool) -> i3
                 format!("Signaled: {:?}", sig)
             };
             cleanup_process_groups(sh, gid, npid.into(), &reason);
-            status = sig as i32;
+            status = 128 + sig as i32;
         }
         Ok(_info) => {
             // log!("waitpid ok: {:?}", _info);
@@ -110,3 +110,20 @@ pub fn try_wait_bg_jobs(sh: &mut shell::Shell) {
         }
     }
 }
+
+/// Close the pipes of process substitutions like `<(ls)` started after the
+/// first `num` ones, and wait for their processes.
+pub fn wait_proc_subs(sh: &mut shell::Shell, num: usize) {
+    if sh.proc_subs.len() <= num {
+        return;
+    }
+    let proc_subs = sh.proc_subs.split_off(num);
+    for (fd, _) in proc_subs.iter() {
+        unsafe {
+            libc::close(*fd);
+        }
+    }
+    for (_, pid) in proc_subs.iter() {
+        wait_process(sh, *pid, *pid, true);
+    }
+}
@@ -65,13 +65,16 @@ mod types;
 #[macro_use]
 mod tools;
 
+mod arith;
 mod builtins;
 mod execute;
 mod history;
 mod jobc;
 mod libs;
 mod parsers;
+mod scripting;
 mod shell;
+mod signals;
 
 /// Represents an error calling `exec`.
 pub use types::CommandResult;
@@ -15,6 +15,7 @@ extern crate nom;
 
 use std::env;
 use std::io::Write;
+use std::process;
 use std::sync::Arc;
 
 use linefeed::{Interface, ReadResult};
@@ -22,6 +23,7 @@ use linefeed::{Interface, ReadResult};
 #[macro_use]
 mod tools;
 
+mod arith;
 mod builtins;
 mod completers;
 mod execute;
@@ -31,7 +33,9 @@ mod libs;
 mod parsers;
 mod prompt;
 mod rcfile;
+mod scripting;
 mod shell;
+mod signals;
 mod types;
 
 use crate::tools::clog;
@@ -50,21 +54,39 @@ fn main() {
     // this section handles `cicada -c 'echo hi && echo yoo'`,
     // e.g. it could be triggered from Vim (`:!ls` etc).
     if args.len() > 1 {
+        sh.non_interactive = true;
         if args[1] != "-c" {
-            println_stderr!("cicada: run script: to be implemented");
-            return;
+            // e.g. `cicada foo.sh arg1 arg2`, or via shebang lines
+            // like `#!/usr/bin/env cicada`.
+            let status = scripting::run_script(&mut sh, &args[1..]);
+            builtins::trap::run_exit(&mut sh);
+            process::exit(status);
+        }
+        if args.len() < 3 {
+            println_stderr!("cicada: -c: option requires an argument");
+            process::exit(2);
         }
-        let line = tools::env_args_to_command_line();
+        // `cicada -c 'echo $0 $1' foo bar` prints "foo bar"
+        let line = args[2].clone();
+        sh.args = if args.len() > 3 {
+            args[3..].to_vec()
+        } else {
+            vec![args[0].clone()]
+        };
         log!("run with -c args: {}", &line);
-        execute::run_procs(&mut sh, &line, false);
-        return;
+        let status = execute::run_procs(&mut sh, &line, false);
+        builtins::trap::run_exit(&mut sh);
+        process::exit(status);
     }
 
+    sh.args = vec![args[0].clone()];
     let isatty: bool = unsafe { libc::isatty(0) == 1 };
     if !isatty {
+        sh.non_interactive = true;
         // cases like open a new MacVim window,
         // (i.e. CMD+N) on an existing one
         execute::handle_non_tty(&mut sh);
+        builtins::trap::run_exit(&mut sh);
         return;
     }
 
@@ -83,6 +105,9 @@ fn main() {
     }));
 
     loop {
+        // signals caught while waiting for input, see `trap`
+        builtins::trap::run_pending(&mut sh);
+
         let prompt = prompt::get_prompt(&sh);
         match rl.set_prompt(&prompt) {
             Ok(_) => {}
@@ -97,14 +122,40 @@ fn main() {
                 if line.trim() == "" {
                     continue;
                 }
+
+                // keep reading lines for multi-line commands, e.g.
+                // here-documents or `if` ... `fi`
+                let mut line = line.clone();
+                let mut aborted = false;
+                while !tools::is_arithmetic(&line) && !parsers::parser_script::is_complete(&line) {
+                    rl.set_prompt("> ").ok();
+                    match rl.read_line() {
+                        Ok(ReadResult::Input(x)) => {
+                            line.push('\n');
+                            line.push_str(&x);
+                        }
+                        _ => {
+                            aborted = true;
+                            break;
+                        }
+                    }
+                }
+                if aborted {
+                    println!();
+                    continue;
+                }
                 sh.cmd = line.clone();
 
                 let tsb_spec = time::get_time();
                 let tsb = (tsb_spec.sec as f64) + tsb_spec.nsec as f64 / 1_000_000_000.0;
 
-                let mut line = line.clone();
                 tools::extend_bandband(&sh, &mut line);
-                let status = execute::run_procs(&mut sh, &line, true);
+                // only lines typed at the prompt go to the calculator
+                let status = if tools::is_arithmetic(&line) {
+                    execute::run_calc(&mut sh, &line)
+                } else {
+                    execute::run_procs(&mut sh, &line, true)
+                };
 
                 let tse_spec = time::get_time();
                 let tse = (tse_spec.sec as f64) + tse_spec.nsec as f64 / 1_000_000_000.0;
@@ -118,6 +169,7 @@ fn main() {
                     }
                 }
                 println!("exit");
+                builtins::trap::run_exit(&mut sh);
                 break;
             }
             Ok(ReadResult::Signal(s)) => {
@@ -1,36 +1,136 @@
 // via: https://github.com/Geal/nom/blob/master/tests/arithmetic.rs
 #![allow(unused_parens)]
-use nom::digit;
+use nom::{alpha, digit};
 
 // Parser definition
 
 use std::str;
 use std::str::FromStr;
 
+/// Values are results, so that errors like division by zero could be
+/// reported.
+pub type Value = Result<f64, String>;
+
+fn calc(op: &[u8], left: Value, right: Value) -> Value {
+    let (left, right) = (left?, right?);
+    let result = match op {
+        b"+" => left + right,
+        b"-" => left - right,
+        b"*" => left * right,
+        b"/" | b"%" => {
+            if right == 0.0 {
+                return Err(String::from("division by zero"));
+            }
+            if op == b"/" {
+                left / right
+            } else {
+                left % right
+            }
+        }
+        b"**" => left.powf(right),
+        _ => return Err(format!("unknown operator: {}", String::from_utf8_lossy(op))),
+    };
+    Ok(result)
+}
+
+/// Functions like `sqrt(2)` and `pow(2, 0.5)`, `log` is the common
+/// logarithm, and `ln` the natural one.
+fn call_func(name: &[u8], args: Vec<Value>) -> Value {
+    let name = String::from_utf8_lossy(name);
+    let args = args.into_iter().collect::<Result<Vec<f64>, String>>()?;
+    let result = match (name.as_ref(), args.as_slice()) {
+        ("sqrt", [x]) => x.sqrt(),
+        ("cbrt", [x]) => x.cbrt(),
+        ("abs", [x]) => x.abs(),
+        ("floor", [x]) => x.floor(),
+        ("ceil", [x]) => x.ceil(),
+        ("round", [x]) => x.round(),
+        ("exp", [x]) => x.exp(),
+        ("ln", [x]) => x.ln(),
+        ("log", [x]) => x.log10(),
+        ("log2", [x]) => x.log2(),
+        ("sin", [x]) => x.sin(),
+        ("cos", [x]) => x.cos(),
+        ("tan", [x]) => x.tan(),
+        ("asin", [x]) => x.asin(),
+        ("acos", [x]) => x.acos(),
+        ("atan", [x]) => x.atan(),
+        ("pow", [x, y]) => x.powf(*y),
+        ("log", [x, base]) => x.log(*base),
+        _ => {
+            return Err(format!(
+                "unknown function: {}() with {} argument(s)",
+                name,
+                args.len()
+            ))
+        }
+    };
+    Ok(result)
+}
+
 // We parse any expr_float surrounded by parens, ignoring all whitespaces around those
 named!(
-    parens<f64>,
+    parens<Value>,
     ws!(delimited!(tag!("("), expr_float, tag!(")")))
 );
 
+// function calls like `sqrt(2)` and `log2(1024)`
+named!(
+    func<Value>,
+    ws!(do_parse!(
+        name: recognize!(pair!(alpha, opt!(complete!(digit))))
+            >> tag!("(")
+            >> args: separated_list!(tag!(","), expr_float)
+            >> tag!(")")
+            >> (call_func(name, args))
+    ))
+);
+
 // We transform an integer string into a f64, ignoring surrounding whitespaces
 // We look for a digit suite, and try to convert it.
 // If either str::from_utf8 or FromStr::from_str fail,
-// we fallback to the parens parser defined above
+// we fallback to the function and parens parsers defined above
 named!(
-    factor<f64>,
+    factor<Value>,
     alt!(
-        map_res!(
+        map!(
             map_res!(
-                ws!(recognize!(alt!(
-                    delimited!(digit, complete!(tag!(".")), opt!(complete!(digit)))
-                        | delimited!(opt!(digit), complete!(tag!(".")), digit)
-                        | digit
-                ))),
-                str::from_utf8
+                map_res!(
+                    ws!(recognize!(alt!(
+                        delimited!(digit, complete!(tag!(".")), opt!(complete!(digit)))
+                            | delimited!(opt!(digit), complete!(tag!(".")), digit)
+                            | digit
+                    ))),
+                    str::from_utf8
+                ),
+                FromStr::from_str
             ),
-            FromStr::from_str
-        ) | parens
+            Ok
+        ) | func
+            | parens
+    )
+);
+
+// `**` binds tighter than unary operators on its left, and is right
+// associative: `-2 ** 2` is `-4`, `2 ** 3 ** 2` is `2 ** 9`
+named!(
+    power<Value>,
+    do_parse!(
+        init: factor
+            >> res: opt!(complete!(preceded!(tag!("**"), unary)))
+            >> (match res {
+                Some(x) => calc(b"**", init, x),
+                None => init,
+            })
+    )
+);
+
+named!(
+    unary<Value>,
+    alt!(
+        ws!(preceded!(tag!("-"), unary)) => { |x: Value| x.map(|v| -v) }
+        | ws!(preceded!(tag!("+"), unary))
+        | power
     )
 );
 
@@ -38,30 +138,24 @@ named!(
 // a * or / operator followed by another factor, we do
 // the math by folding everything
 named!(
-    term<f64>,
+    term<Value>,
     do_parse!(
-        init: factor
+        init: unary
             >> res: fold_many0!(
-                pair!(alt!(tag!("*") | tag!("/")), factor),
+                pair!(alt!(tag!("*") | tag!("/") | tag!("%")), unary),
                 init,
-                |acc, (op, val): (&[u8], f64)| if (op[0] as char) == '*' {
-                    (acc * val) as f64
-                } else {
-                    (acc / val) as f64
-                }
+                |acc, (op, val): (&[u8], Value)| calc(op, acc, val)
             )
             >> (res)
     )
 );
 
-named!(pub expr_float <f64>, do_parse!(
+named!(pub expr_float <Value>, do_parse!(
     init: term >>
     res:  fold_many0!(
         pair!(alt!(tag!("+") | tag!("-")), term),
         init,
-        |acc, (op, val): (&[u8], f64)| {
-            if (op[0] as char) == '+' { (acc + val) as f64 } else { (acc - val) as f64 }
-        }
+        |acc, (op, val): (&[u8], Value)| calc(op, acc, val)
     ) >>
     (res)
   )
@@ -74,46 +168,80 @@ mod tests {
 
     #[test]
     fn factor_test() {
-        assert_eq!(factor(&b"3"[..]), IResult::Done(&b""[..], 3.0));
-        assert_eq!(factor(&b"3 "[..]), IResult::Done(&b""[..], 3.0));
-        assert_eq!(factor(&b" 12"[..]), IResult::Done(&b""[..], 12.0));
-        assert_eq!(factor(&b"537  "[..]), IResult::Done(&b""[..], 537.0));
-        assert_eq!(factor(&b"  24   "[..]), IResult::Done(&b""[..], 24.0));
+        assert_eq!(factor(&b"3"[..]), IResult::Done(&b""[..], Ok(3.0)));
+        assert_eq!(factor(&b"3 "[..]), IResult::Done(&b""[..], Ok(3.0)));
+        assert_eq!(factor(&b" 12"[..]), IResult::Done(&b""[..], Ok(12.0)));
+        assert_eq!(factor(&b"537  "[..]), IResult::Done(&b""[..], Ok(537.0)));
+        assert_eq!(factor(&b"  24   "[..]), IResult::Done(&b""[..], Ok(24.0)));
     }
 
     #[test]
     fn term_test() {
-        assert_eq!(term(&b" 12 *2 /  3"[..]), IResult::Done(&b""[..], 8.0));
+        assert_eq!(term(&b" 12 *2 /  3"[..]), IResult::Done(&b""[..], Ok(8.0)));
         assert_eq!(
             term(&b" 2* 3  *2 *2 /  3"[..]),
-            IResult::Done(&b""[..], 8.0)
+            IResult::Done(&b""[..], Ok(8.0))
         );
-        assert_eq!(term(&b" 48 /  3/2"[..]), IResult::Done(&b""[..], 8.0));
+        assert_eq!(term(&b" 48 /  3/2"[..]), IResult::Done(&b""[..], Ok(8.0)));
     }
 
     #[test]
     fn expr_test() {
-        assert_eq!(expr_float(&b" 1 +  2 "[..]), IResult::Done(&b""[..], 3.0));
+        assert_eq!(
+            expr_float(&b" 1 +  2 "[..]),
+            IResult::Done(&b""[..], Ok(3.0))
+        );
         assert_eq!(
             expr_float(&b" 12 + 6 - 4+  3"[..]),
-            IResult::Done(&b""[..], 17.0)
+            IResult::Done(&b""[..], Ok(17.0))
         );
         assert_eq!(
             expr_float(&b" 1 + 2*3 + 4"[..]),
-            IResult::Done(&b""[..], 11.0)
+            IResult::Done(&b""[..], Ok(11.0))
         );
     }
 
     #[test]
     fn parens_test() {
-        assert_eq!(expr_float(&b" (  2 )"[..]), IResult::Done(&b""[..], 2.0));
+        assert_eq!(
+            expr_float(&b" (  2 )"[..]),
+            IResult::Done(&b""[..], Ok(2.0))
+        );
         assert_eq!(
             expr_float(&b" 2* (  3 + 4 ) "[..]),
-            IResult::Done(&b""[..], 14.0)
+            IResult::Done(&b""[..], Ok(14.0))
         );
         assert_eq!(
             expr_float(&b"  2*2 / ( 5 - 1) + 3"[..]),
-            IResult::Done(&b""[..], 4.0)
+            IResult::Done(&b""[..], Ok(4.0))
+        );
+    }
+
+    #[test]
+    fn functions_test() {
+        assert_eq!(
+            expr_float(&b"sqrt(16)"[..]),
+            IResult::Done(&b""[..], Ok(4.0))
+        );
+        assert_eq!(
+            expr_float(&b" pow(2, 10) - log(100) "[..]),
+            IResult::Done(&b""[..], Ok(1022.0))
+        );
+        assert_eq!(
+            expr_float(&b"log2(8) ** 2 % 5"[..]),
+            IResult::Done(&b""[..], Ok(4.0))
+        );
+        assert_eq!(
+            expr_float(&b"-sin(0)"[..]),
+            IResult::Done(&b""[..], Ok(-0.0))
+        );
+        assert_eq!(
+            expr_float(&b"1.5 / (1 - 1)"[..]),
+            IResult::Done(&b""[..], Err(String::from("division by zero")))
         );
+        assert!(match expr_float(&b"sqrt(1, 2)"[..]) {
+            IResult::Done(_, x) => x.is_err(),
+            _ => false,
+        });
     }
 }
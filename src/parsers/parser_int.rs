@@ -1,33 +1,103 @@
 // via: https://github.com/Geal/nom/blob/master/tests/arithmetic.rs
 #![allow(unused_parens)]
-use nom::digit;
+use nom::{digit, hex_digit, oct_digit};
 
 // Parser definition
 
 use std::str;
-use std::str::FromStr;
+
+/// Values are results, so that errors like division by zero could be
+/// reported instead of panicking.
+pub type Value = Result<i64, String>;
+
+fn calc(op: &[u8], left: Value, right: Value) -> Value {
+    let (left, right) = (left?, right?);
+    let result = match op {
+        b"+" => left.checked_add(right),
+        b"-" => left.checked_sub(right),
+        b"*" => left.checked_mul(right),
+        b"/" | b"%" => {
+            if right == 0 {
+                return Err(String::from("division by zero"));
+            }
+            if op == b"/" {
+                left.checked_div(right)
+            } else {
+                left.checked_rem(right)
+            }
+        }
+        b"**" => {
+            if right < 0 {
+                return Err(String::from("negative exponent"));
+            }
+            left.checked_pow(right.min(u32::MAX as i64) as u32)
+        }
+        b"<<" => Some(left.wrapping_shl(right as u32)),
+        b">>" => Some(left.wrapping_shr(right as u32)),
+        b"&" => Some(left & right),
+        b"|" => Some(left | right),
+        b"^" => Some(left ^ right),
+        _ => return Err(format!("unknown operator: {}", String::from_utf8_lossy(op))),
+    };
+    result.ok_or_else(|| String::from("integer overflow"))
+}
+
+fn neg(x: i64) -> Value {
+    x.checked_neg()
+        .ok_or_else(|| String::from("integer overflow"))
+}
+
+fn from_radix(digits: &[u8], radix: u32) -> Result<i64, String> {
+    let digits = str::from_utf8(digits).map_err(|e| e.to_string())?;
+    i64::from_str_radix(digits, radix).map_err(|e| e.to_string())
+}
 
 // We parse any expr_int surrounded by parens, ignoring all whitespaces around those
-named!(parens<i64>, ws!(delimited!(tag!("("), expr_int, tag!(")"))));
+named!(
+    parens<Value>,
+    ws!(delimited!(tag!("("), expr_int, tag!(")")))
+);
 
-// We transform an integer string into a i64, ignoring surrounding whitespaces
-// We look for a digit suite, and try to convert it.
-// If either str::from_utf8 or FromStr::from_str fail,
-// we fallback to the parens parser defined above
+// We transform an integer string into a i64, ignoring surrounding whitespaces.
+// Literals could also be hexadecimal, octal or binary, like `0x1f`, `0o17`
+// and `0b101`. If none of them matches, we fallback to the parens parser
+// defined above
 named!(
-    factor<i64>,
+    factor<Value>,
     alt!(
-        map_res!(
-            map_res!(
-                ws!(recognize!(alt!(
-                    delimited!(digit, complete!(tag!(".")), opt!(complete!(digit)))
-                        | delimited!(opt!(digit), complete!(tag!(".")), digit)
-                        | digit
-                ))),
-                str::from_utf8
-            ),
-            FromStr::from_str
-        ) | parens
+        ws!(alt!(
+            complete!(preceded!(alt!(tag!("0x") | tag!("0X")), hex_digit))
+                => { |x| from_radix(x, 16) }
+            | complete!(preceded!(alt!(tag!("0o") | tag!("0O")), oct_digit))
+                => { |x| from_radix(x, 8) }
+            | complete!(preceded!(alt!(tag!("0b") | tag!("0B")), is_a!("01")))
+                => { |x| from_radix(x, 2) }
+            | digit => { |x| from_radix(x, 10) }
+        )) | parens
+    )
+);
+
+// `**` binds tighter than unary operators on its left, and is right
+// associative: `-2 ** 2` is `-4`, `2 ** 3 ** 2` is `2 ** 9`
+named!(
+    power<Value>,
+    do_parse!(
+        init: factor
+            >> res: opt!(complete!(preceded!(tag!("**"), unary)))
+            >> (match res {
+                Some(x) => calc(b"**", init, x),
+                None => init,
+            })
+    )
+);
+
+named!(
+    unary<Value>,
+    alt!(
+        ws!(preceded!(tag!("-"), unary)) => { |x: Value| x.and_then(neg) }
+        | ws!(preceded!(tag!("+"), unary))
+        | ws!(preceded!(tag!("~"), unary)) => { |x: Value| x.map(|v| !v) }
+        | power
     )
 );
 
@@ -35,30 +105,76 @@ named!(
 // a * or / operator followed by another factor, we do
 // the math by folding everything
 named!(
-    term<i64>,
+    term<Value>,
     do_parse!(
-        init: factor
+        init: unary
             >> res: fold_many0!(
-                pair!(alt!(tag!("*") | tag!("/")), factor),
+                pair!(alt!(tag!("*") | tag!("/") | tag!("%")), unary),
                 init,
-                |acc, (op, val): (&[u8], i64)| if (op[0] as char) == '*' {
-                    (acc * val) as i64
-                } else {
-                    (acc / val) as i64
-                }
+                |acc, (op, val): (&[u8], Value)| calc(op, acc, val)
             )
             >> (res)
     )
 );
 
-named!(pub expr_int <i64>, do_parse!(
-    init: term >>
+named!(
+    additive<Value>,
+    do_parse!(
+      init: term >>
+      res:  fold_many0!(
+          pair!(alt!(tag!("+") | tag!("-")), term),
+          init,
+          |acc, (op, val): (&[u8], Value)| calc(op, acc, val)
+      ) >>
+      (res)
+    )
+);
+
+named!(
+    shift<Value>,
+    do_parse!(
+      init: additive >>
+      res:  fold_many0!(
+          pair!(alt!(complete!(tag!("<<")) | complete!(tag!(">>"))), additive),
+          init,
+          |acc, (op, val): (&[u8], Value)| calc(op, acc, val)
+      ) >>
+      (res)
+    )
+);
+
+named!(
+    bit_and<Value>,
+    do_parse!(
+      init: shift >>
+      res:  fold_many0!(
+          pair!(tag!("&"), shift),
+          init,
+          |acc, (op, val): (&[u8], Value)| calc(op, acc, val)
+      ) >>
+      (res)
+    )
+);
+
+named!(
+    bit_xor<Value>,
+    do_parse!(
+      init: bit_and >>
+      res:  fold_many0!(
+          pair!(tag!("^"), bit_and),
+          init,
+          |acc, (op, val): (&[u8], Value)| calc(op, acc, val)
+      ) >>
+      (res)
+    )
+);
+
+named!(pub expr_int <Value>, do_parse!(
+    init: bit_xor >>
     res:  fold_many0!(
-        pair!(alt!(tag!("+") | tag!("-")), term),
+        pair!(tag!("|"), bit_xor),
         init,
-        |acc, (op, val): (&[u8], i64)| {
-            if (op[0] as char) == '+' { (acc + val) as i64 } else { (acc - val) as i64 }
-        }
+        |acc, (op, val): (&[u8], Value)| calc(op, acc, val)
     ) >>
     (res)
   )
@@ -71,44 +187,92 @@ mod tests {
 
     #[test]
     fn factor_test() {
-        assert_eq!(factor(&b"3"[..]), IResult::Done(&b""[..], 3));
-        assert_eq!(factor(&b"3 "[..]), IResult::Done(&b""[..], 3));
-        assert_eq!(factor(&b" 12"[..]), IResult::Done(&b""[..], 12));
-        assert_eq!(factor(&b"537  "[..]), IResult::Done(&b""[..], 537));
-        assert_eq!(factor(&b"  24   "[..]), IResult::Done(&b""[..], 24));
+        assert_eq!(factor(&b"3"[..]), IResult::Done(&b""[..], Ok(3)));
+        assert_eq!(factor(&b"3 "[..]), IResult::Done(&b""[..], Ok(3)));
+        assert_eq!(factor(&b" 12"[..]), IResult::Done(&b""[..], Ok(12)));
+        assert_eq!(factor(&b"537  "[..]), IResult::Done(&b""[..], Ok(537)));
+        assert_eq!(factor(&b"  24   "[..]), IResult::Done(&b""[..], Ok(24)));
+        assert_eq!(factor(&b"0x1F"[..]), IResult::Done(&b""[..], Ok(31)));
+        assert_eq!(factor(&b" 0o17 "[..]), IResult::Done(&b""[..], Ok(15)));
+        assert_eq!(factor(&b"0b101"[..]), IResult::Done(&b""[..], Ok(5)));
     }
 
     #[test]
     fn term_test() {
-        assert_eq!(term(&b" 12 *2 /  3"[..]), IResult::Done(&b""[..], 8));
-        assert_eq!(term(&b" 2* 3  *2 *2 /  3"[..]), IResult::Done(&b""[..], 8));
-        assert_eq!(term(&b" 48 /  3/2"[..]), IResult::Done(&b""[..], 8));
+        assert_eq!(term(&b" 12 *2 /  3"[..]), IResult::Done(&b""[..], Ok(8)));
+        assert_eq!(
+            term(&b" 2* 3  *2 *2 /  3"[..]),
+            IResult::Done(&b""[..], Ok(8))
+        );
+        assert_eq!(term(&b" 48 /  3/2"[..]), IResult::Done(&b""[..], Ok(8)));
     }
 
     #[test]
     fn expr_test() {
-        assert_eq!(expr_int(&b" 1 +  2 "[..]), IResult::Done(&b""[..], 3));
+        assert_eq!(expr_int(&b" 1 +  2 "[..]), IResult::Done(&b""[..], Ok(3)));
         assert_eq!(
             expr_int(&b" 12 + 6 - 4+  3"[..]),
-            IResult::Done(&b""[..], 17)
+            IResult::Done(&b""[..], Ok(17))
+        );
+        assert_eq!(
+            expr_int(&b" 1 + 2*3 + 4"[..]),
+            IResult::Done(&b""[..], Ok(11))
         );
-        assert_eq!(expr_int(&b" 1 + 2*3 + 4"[..]), IResult::Done(&b""[..], 11));
     }
 
     #[test]
     fn parens_test() {
-        assert_eq!(expr_int(&b" (  2 )"[..]), IResult::Done(&b""[..], 2));
+        assert_eq!(expr_int(&b" (  2 )"[..]), IResult::Done(&b""[..], Ok(2)));
         assert_eq!(
             expr_int(&b" 2* (  3 + 4 ) "[..]),
-            IResult::Done(&b""[..], 14)
+            IResult::Done(&b""[..], Ok(14))
         );
         assert_eq!(
             expr_int(&b"  2*2 / ( 5 - 1) + 3"[..]),
-            IResult::Done(&b""[..], 4)
+            IResult::Done(&b""[..], Ok(4))
         );
         assert_eq!(
             expr_int(&b"4611686018400000000 + 1"[..]),
-            IResult::Done(&b""[..], 4611686018400000001)
+            IResult::Done(&b""[..], Ok(4611686018400000001))
+        );
+    }
+
+    #[test]
+    fn operators_test() {
+        assert_eq!(expr_int(&b"7 % 4 * 2"[..]), IResult::Done(&b""[..], Ok(6)));
+        assert_eq!(
+            expr_int(&b"2 ** 3 ** 2"[..]),
+            IResult::Done(&b""[..], Ok(512))
+        );
+        assert_eq!(expr_int(&b"-2 ** 2"[..]), IResult::Done(&b""[..], Ok(-4)));
+        assert_eq!(expr_int(&b"2 * -3"[..]), IResult::Done(&b""[..], Ok(-6)));
+        assert_eq!(
+            expr_int(&b"1 << 4 >> 1"[..]),
+            IResult::Done(&b""[..], Ok(8))
         );
+        assert_eq!(
+            expr_int(&b"~5 & 0xf | 1"[..]),
+            IResult::Done(&b""[..], Ok(11))
+        );
+        assert_eq!(expr_int(&b"6 ^ 3"[..]), IResult::Done(&b""[..], Ok(5)));
+        assert_eq!(
+            expr_int(&b"1 + 2 / 0"[..]),
+            IResult::Done(&b""[..], Err(String::from("division by zero")))
+        );
+        assert_eq!(
+            expr_int(&b"2 ** -1"[..]),
+            IResult::Done(&b""[..], Err(String::from("negative exponent")))
+        );
+        for line in [
+            "2 ** 63",
+            "2 ** 64",
+            "0x7fffffffffffffff + 1",
+            "-(-0x7fffffffffffffff - 1)",
+        ] {
+            assert_eq!(
+                expr_int(line.as_bytes()),
+                IResult::Done(&b""[..], Err(String::from("integer overflow")))
+            );
+        }
     }
 }
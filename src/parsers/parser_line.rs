@@ -1,7 +1,9 @@
 use regex::Regex;
 
+use crate::parsers::parser_script;
 use crate::tools;
 use crate::types::Command;
+use crate::types::Redirection;
 use crate::types::Tokens;
 
 pub fn line_to_plain_tokens(line: &str) -> Vec<String> {
@@ -24,6 +26,10 @@ pub fn tokens_to_args(tokens: &Tokens) -> Vec<String> {
 pub fn tokens_to_line(tokens: &Tokens) -> String {
     let mut result = String::new();
     for t in tokens {
+        if t.0 == "(" {
+            result.push_str(format!("({}) ", t.1).as_str());
+            continue;
+        }
         result.push_str(format!("{}{}{} ", t.0, t.1, t.0).as_str());
     }
     if result.ends_with(' ') {
@@ -33,113 +39,90 @@ pub fn tokens_to_line(tokens: &Tokens) -> String {
     result
 }
 
-/// Parse command line for multiple commands. Examples:
-/// >>> line_to_cmds("echo foo && echo bar; echo end");
-/// vec!["echo foo", "&&", "echo bar", ";", "echo end"]
-/// >>> line_to_cmds("man awk | grep version");
-/// vec!["man awk | grep version"]
-pub fn line_to_cmds(line: &str) -> Vec<String> {
-    // Special characters: http://tldp.org/LDP/abs/html/special-chars.html
-    let mut result = Vec::new();
-    let mut sep = String::new();
-    let mut token = String::new();
-    let mut has_backslash = false;
-    let len = line.len();
-    for (i, c) in line.chars().enumerate() {
-        if has_backslash {
-            token.push('\\');
-            token.push(c);
-            has_backslash = false;
-            continue;
+/// Parse here-documents like `<<EOF\nfoo\nEOF\n`, which is how
+/// `parser_script` keeps them in commands. Returns the token of the body
+/// and the index right after it. The body is quoted with `'` when the
+/// delimiter is quoted (no expansions), or with `"` otherwise.
+fn parse_here_document(chars: &[char], start: usize) -> Option<((String, String), usize)> {
+    let text: String = chars[start..].iter().collect();
+    let re = Regex::new(r"^<<(-?)[ \t]*([^<\n][^\n]*)\n").ok()?;
+    let caps = re.captures(&text)?;
+    let strip_tabs = &caps[1] == "-";
+    let spec = caps[2].trim_end();
+    let quoted = spec.contains(['\'', '"', '\\']);
+    let delimiter: String = spec
+        .chars()
+        .filter(|&x| x != '\'' && x != '"' && x != '\\')
+        .collect();
+
+    let mut body = String::new();
+    let mut i = start + caps[0].chars().count();
+    loop {
+        if i >= chars.len() {
+            return None;
         }
+        let end = chars[i..]
+            .iter()
+            .position(|&c| c == '\n')
+            .map_or(chars.len(), |x| i + x);
+        let line: String = chars[i..end].iter().collect();
+        i = end + 1;
+        let line = if strip_tabs {
+            line.trim_start_matches('\t')
+        } else {
+            line.as_str()
+        };
+        if line == delimiter {
+            break;
+        }
+        body.push_str(line);
+        body.push('\n');
+    }
+    let sep = if quoted { "'" } else { "\"" };
+    Some(((sep.to_string(), body), i.min(chars.len())))
+}
 
-        if c == '\\' && sep != "'" {
-            has_backslash = true;
+/// Returns index of the `)` matching the `(` at `start`, or the length
+/// of `chars` if it is missing.
+pub fn find_closing_parenthesis(chars: &[char], start: usize) -> usize {
+    // command substitutions like `$(case $x in a) ...` are scanned by the
+    // script parser
+    if start > 0 && chars[start - 1] == '$' && chars.get(start + 1) != Some(&'(') {
+        return parser_script::scan_command_substitution(chars, start - 1)
+            .map_or(chars.len(), |x| x - 1);
+    }
+    let mut depth = 0;
+    let mut quote = '\0';
+    let mut i = start;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && quote != '\'' {
+            i += 2;
             continue;
         }
-
-        if c == '#' {
-            if sep.is_empty() {
-                break;
-            } else {
-                token.push(c);
-                continue;
+        if quote != '\0' {
+            if c == quote {
+                quote = '\0';
             }
-        }
-        if c == '\'' || c == '"' || c == '`' {
-            if sep.is_empty() {
-                sep.push(c);
-                token.push(c);
-                continue;
-            } else if sep == c.to_string() {
-                token.push(c);
-                sep = String::new();
-                continue;
-            } else {
-                token.push(c);
+        } else if c == '\'' || c == '"' || c == '`' {
+            quote = c;
+        } else if c == '<' {
+            // quotes in bodies of here-documents do not count
+            if let Some((_, end)) = parse_here_document(chars, i) {
+                i = end;
                 continue;
             }
-        }
-        if c == '&' || c == '|' {
-            // needs watch ahead here
-            if sep.is_empty() {
-                if i + 1 == len {
-                    // for bg commands, e.g. `ls &`
-                    token.push(c);
-                    continue;
-                } else {
-                    let c_next;
-                    match line.chars().nth(i + 1) {
-                        Some(x) => c_next = x,
-                        None => {
-                            println!("chars nth error - should never happen");
-                            continue;
-                        }
-                    }
-                    if c_next != c {
-                        token.push(c);
-                        continue;
-                    }
-                }
-            }
-
-            if sep.is_empty() {
-                sep.push(c);
-                continue;
-            } else if c.to_string() == sep {
-                let _token = token.trim().to_string();
-                if !_token.is_empty() {
-                    result.push(_token);
-                }
-                token = String::new();
-                result.push(format!("{}{}", sep, sep));
-                sep = String::new();
-                continue;
-            } else {
-                token.push(c);
-                continue;
+        } else if c == '(' {
+            depth += 1;
+        } else if c == ')' {
+            depth -= 1;
+            if depth == 0 {
+                return i;
             }
         }
-        if c == ';' {
-            if sep.is_empty() {
-                let _token = token.trim().to_string();
-                if !_token.is_empty() {
-                    result.push(_token);
-                }
-                result.push(String::from(";"));
-                token = String::new();
-                continue;
-            } else {
-                token.push(c);
-                continue;
-            }
-        }
-        token.push(c);
+        i += 1;
     }
-    if !token.is_empty() {
-        result.push(token.trim().to_string());
-    }
-    result
+    chars.len()
 }
 
 /// parse command line to tokens
@@ -161,10 +144,10 @@ pub fn cmd_to_tokens(line: &str) -> Tokens {
     let mut sep_second = String::new();
     let mut token = String::new();
     let mut has_backslash = false;
-    let mut met_parenthesis = false;
     let mut new_round = true;
     let mut skip_next = false;
     let mut has_dollar = false;
+    let mut brace_depth = 0;
     // using semi_ok makes quite dirty here
     // it is mainly for path completion like:
     // $ ls "foo b<TAB>
@@ -175,12 +158,54 @@ pub fn cmd_to_tokens(line: &str) -> Tokens {
     // also using semi_ok makes the following command works as expected:
     // $ touch "foo"/bar.txt  # create bar.txt under ./foo directory
     let mut semi_ok = false;
+    // for subshells like `(cd foo && make)`
+    let mut skip_to = 0;
     let count_chars = line.chars().count();
     for (i, c) in line.chars().enumerate() {
         if skip_next {
             skip_next = false;
             continue;
         }
+        if i < skip_to {
+            continue;
+        }
+
+        if c == '(' && new_round && !has_backslash {
+            let chars: Vec<char> = line.chars().collect();
+            let end = find_closing_parenthesis(&chars, i);
+            let inner: String = chars[i + 1..end].iter().collect();
+            result.push((String::from("("), inner));
+            skip_to = end + 1;
+            continue;
+        }
+
+        // process substitutions like `<(ls)` and `>(wc)`
+        if (c == '<' || c == '>') && new_round && !has_backslash {
+            let chars: Vec<char> = line.chars().collect();
+            if i + 1 < count_chars && chars[i + 1] == '(' {
+                let end = find_closing_parenthesis(&chars, i + 1);
+                let inner: String = chars[i + 2..end].iter().collect();
+                result.push((format!("{}(", c), inner));
+                skip_to = end + 1;
+                continue;
+            }
+        }
+
+        if c == '<' && new_round && !has_backslash {
+            let chars: Vec<char> = line.chars().collect();
+            // here-strings like `cat <<<"$foo"`
+            if chars[i..].starts_with(&['<', '<', '<']) {
+                result.push((String::new(), String::from("<<<")));
+                skip_to = i + 3;
+                continue;
+            }
+            if let Some((body, end)) = parse_here_document(&chars, i) {
+                result.push((String::new(), String::from("<<")));
+                result.push(body);
+                skip_to = end;
+                continue;
+            }
+        }
 
         if has_backslash && sep == "\"" && c != '\"' {
             // constant with bash: "\"" --> "; "\a" --> \a
@@ -206,20 +231,38 @@ pub fn cmd_to_tokens(line: &str) -> Tokens {
             has_dollar = true;
         }
 
-        // for cases like: echo $(foo bar)
-        if c == '(' && sep.is_empty() {
-            if !has_dollar {
-                // temp solution for cmd like `(ls)`, `(ls -lh)`
-                continue;
-            }
-            met_parenthesis = true;
+        // command substitutions like `$(dirname "$(which ls)")` are kept
+        // in the token as they are, with the nested ones and quotes
+        if c == '(' && sep != "'" && token.ends_with('$') && !token.ends_with("\\$") {
+            let chars: Vec<char> = line.chars().collect();
+            let end = find_closing_parenthesis(&chars, i);
+            token.extend(chars[i..(end + 1).min(count_chars)].iter());
+            new_round = false;
+            skip_to = end + 1;
+            continue;
         }
-        if c == ')' && sep.is_empty() {
-            if !has_dollar {
-                // temp solution for cmd like `(ls)`, `(ls -lh)`
-                continue;
-            }
-            met_parenthesis = false;
+
+        // arrays like `arr=(a "b c")`
+        if c == '(' && sep.is_empty() && tools::re_contains(&token, r"^[a-zA-Z_][a-zA-Z0-9_]*\+?=$")
+        {
+            let chars: Vec<char> = line.chars().collect();
+            let end = find_closing_parenthesis(&chars, i);
+            token.extend(chars[i..(end + 1).min(count_chars)].iter());
+            skip_to = end + 1;
+            continue;
+        }
+
+        if (c == '(' || c == ')') && sep.is_empty() && sep_second.is_empty() && !has_dollar {
+            // temp solution for cmd like `(ls)`, `(ls -lh)`
+            continue;
+        }
+
+        // for cases like: echo ${foo:-a b}
+        if c == '{' && sep.is_empty() && token.ends_with('$') {
+            brace_depth += 1;
+        }
+        if c == '}' && sep.is_empty() && brace_depth > 0 {
+            brace_depth -= 1;
         }
 
         if c == '\\' && sep != "'" {
@@ -247,6 +290,10 @@ pub fn cmd_to_tokens(line: &str) -> Tokens {
                 if i + 1 < count_chars && line.chars().nth(i + 1).unwrap() == '|' {
                     result.push((String::from(""), "||".to_string()));
                     skip_next = true;
+                } else if line.chars().nth(i + 1) == Some('&') {
+                    // `|&` pipes both stdout and stderr
+                    result.push((String::from(""), "|&".to_string()));
+                    skip_next = true;
                 } else {
                     result.push((String::from(""), "|".to_string()));
                 }
@@ -259,19 +306,32 @@ pub fn cmd_to_tokens(line: &str) -> Tokens {
             continue;
         }
 
+        // `>|` overwrites files even with `set -C`
+        if c == '|' && sep.is_empty() && token.ends_with('>') {
+            token.push(c);
+            continue;
+        }
+
         if c == '|' && !has_backslash {
+            let pipe = if line.chars().nth(i + 1) == Some('&') {
+                "|&"
+            } else {
+                "|"
+            };
             if semi_ok {
                 result.push((sep.to_string(), token));
-                result.push((String::from(""), "|".to_string()));
+                result.push((String::from(""), pipe.to_string()));
+                skip_next = pipe == "|&";
                 sep = String::new();
                 sep_second = String::new();
                 token = String::new();
                 new_round = true;
                 semi_ok = false;
                 continue;
-            } else if !met_parenthesis && sep_second.is_empty() && sep.is_empty() {
+            } else if sep_second.is_empty() && sep.is_empty() {
                 result.push((String::from(""), token));
-                result.push((String::from(""), "|".to_string()));
+                result.push((String::from(""), pipe.to_string()));
+                skip_next = pipe == "|&";
                 sep = String::new();
                 sep_second = String::new();
                 token = String::new();
@@ -297,7 +357,7 @@ pub fn cmd_to_tokens(line: &str) -> Tokens {
                 continue;
             }
 
-            if met_parenthesis {
+            if brace_depth > 0 {
                 token.push(c);
                 continue;
             }
@@ -342,17 +402,13 @@ pub fn cmd_to_tokens(line: &str) -> Tokens {
                 // do not use continue here!
             }
 
-            if sep != c.to_string() && met_parenthesis {
-                token.push(c);
-                continue;
-            }
             if sep.is_empty() && !sep_second.is_empty() && sep_second != c.to_string() {
                 token.push(c);
                 continue;
             }
 
             if sep.is_empty() {
-                let is_an_env = tools::re_contains(&token, r"^[a-zA-Z0-9_]+=.*$");
+                let is_an_env = tools::re_contains(&token, r"^[a-zA-Z0-9_]+(\[[^\]]*\])?\+?=.*$");
                 if !is_an_env && (c == '\'' || c == '"') {
                     sep = c.to_string();
                     continue;
@@ -393,9 +449,36 @@ pub fn cmd_to_with_redirects(tokens: &Tokens) -> Result<Command, String> {
     let mut to_be_continued = false;
     let mut to_be_continued_s1 = String::new();
     let mut to_be_continued_s2 = String::new();
+    let mut here_document = String::new();
+
+    // e.g. `>foo`, `2>>foo`, `3<foo`, `<>foo`, `2>&1`, `3>&-`, `&>foo`
+    // and `>|foo`
+    let re;
+    if let Ok(x) = Regex::new(r"^([^<>]*?)(&>>|&>|>>|<>|>\||>|<)([^<>]*)$") {
+        re = x;
+    } else {
+        return Err(String::from("Failed to build Regex"));
+    }
 
     for token in tokens {
         let sep = &token.0;
+        // `<<` is followed by the body of here-document (see
+        // `parse_here_document()`), and `<<<` by a word
+        if here_document == "<<" {
+            redirects.push((String::from("0"), here_document, token.1.clone()));
+            here_document = String::new();
+            continue;
+        }
+        if here_document == "<<<" {
+            let text = format!("{}\n", token.1);
+            redirects.push((String::from("0"), here_document, text));
+            here_document = String::new();
+            continue;
+        }
+        if sep.is_empty() && (token.1 == "<<" || token.1 == "<<<") {
+            here_document = token.1.clone();
+            continue;
+        }
         if !sep.is_empty() && !to_be_continued {
             tokens_new.push(token.clone());
             continue;
@@ -407,77 +490,49 @@ pub fn cmd_to_with_redirects(tokens: &Tokens) -> Result<Command, String> {
                 return Err(String::from("bad redirection syntax near &"));
             }
 
-            let s3 = format!("{}{}{}", sep, word, sep);
-            if tools::re_contains(&to_be_continued_s1, r"^\d+$") {
-                if to_be_continued_s1 != "1" && to_be_continued_s1 != "2" {
-                    return Err(String::from("Bad file descriptor #3"));
-                }
-                let s1 = to_be_continued_s1.clone();
-                let s2 = to_be_continued_s2.clone();
-                redirects.push((s1, s2, s3));
-            } else {
-                if to_be_continued_s1 != "" {
-                    tokens_new.push((sep.clone(), to_be_continued_s1.to_string()));
-                }
-                redirects.push(("1".to_string(), to_be_continued_s2.clone(), s3));
-            }
-
+            let s1 = to_be_continued_s1.clone();
+            push_redirect(&mut redirects, s1, &to_be_continued_s2, word);
             to_be_continued = false;
             continue;
         }
 
-        let ptn1 = r"^([^>]*)(>>?)([^>]+)$";
-        let ptn2 = r"^([^>]*)(>>?)$";
-        if !tools::re_contains(word, r">") {
-            tokens_new.push(token.clone());
-        } else if tools::re_contains(word, ptn1) {
-            let re;
-            if let Ok(x) = Regex::new(ptn1) {
-                re = x;
-            } else {
-                return Err(String::from("Failed to build Regex"));
-            }
-
-            if let Some(caps) = re.captures(&word) {
-                let s1 = caps.get(1).unwrap().as_str();
-                let s2 = caps.get(2).unwrap().as_str();
-                let s3 = caps.get(3).unwrap().as_str();
-                if s3.starts_with('&') && s3 != "&1" && s3 != "&2" {
-                    return Err(String::from("Bad file descriptor #1"));
-                }
-
-                if tools::re_contains(s1, r"^\d+$") {
-                    if s1 != "1" && s1 != "2" {
-                        return Err(String::from("Bad file descriptor #2"));
-                    }
-                    redirects.push((s1.to_string(), s2.to_string(), s3.to_string()));
-                } else {
-                    if s1 != "" {
-                        tokens_new.push((sep.clone(), s1.to_string()));
-                    }
-                    redirects.push((String::from("1"), s2.to_string(), s3.to_string()));
-                }
-            }
-        } else if tools::re_contains(word, ptn2) {
-            let re;
-            if let Ok(x) = Regex::new(ptn2) {
-                re = x;
-            } else {
-                return Err(String::from("Failed to build Regex"));
+        let caps = match re.captures(word) {
+            Some(x) => x,
+            None => {
+                tokens_new.push(token.clone());
+                continue;
             }
-
-            if let Some(caps) = re.captures(&word) {
-                let s1 = caps.get(1).unwrap().as_str();
-                let s2 = caps.get(2).unwrap().as_str();
-
-                to_be_continued = true;
-                to_be_continued_s1 = s1.to_string();
-                to_be_continued_s2 = s2.to_string();
+        };
+        let s1 = caps.get(1).unwrap().as_str();
+        let s2 = caps.get(2).unwrap().as_str();
+        let s3 = caps.get(3).unwrap().as_str();
+
+        // without a fd number, `>` is for stdout and `<` is for stdin
+        let fd = if !s2.starts_with('&') && tools::re_contains(s1, r"^\d+$") {
+            s1.to_string()
+        } else {
+            if !s1.is_empty() {
+                tokens_new.push((sep.clone(), s1.to_string()));
             }
+            let fd = if s2.starts_with('>') { "1" } else { "0" };
+            fd.to_string()
+        };
+
+        if s3.starts_with('&')
+            && ((s2 != ">" && s2 != "<") || !tools::re_contains(s3, r"^&(\d+|-)$"))
+        {
+            return Err(format!("{}: bad file descriptor", s3));
+        }
+        if s3.is_empty() {
+            to_be_continued = true;
+            to_be_continued_s1 = fd;
+            to_be_continued_s2 = s2.to_string();
+            continue;
         }
+        push_redirect(&mut redirects, fd, s2, s3);
     }
 
-    if to_be_continued {
+    if to_be_continued || !here_document.is_empty() {
         return Err(String::from("redirection syntax error"));
     }
 
@@ -487,6 +542,17 @@ pub fn cmd_to_with_redirects(tokens: &Tokens) -> Result<Command, String> {
     })
 }
 
+/// `&>foo` (and `&>>foo`) is the short form of `>foo 2>&1`.
+fn push_redirect(redirects: &mut Vec<Redirection>, fd: String, op: &str, target: &str) {
+    match op.strip_prefix('&') {
+        Some(op) => {
+            redirects.push((String::from("1"), op.to_string(), target.to_string()));
+            redirects.push((String::from("2"), String::from(">"), String::from("&1")));
+        }
+        None => redirects.push((fd, op.to_string(), target.to_string())),
+    }
+}
+
 pub fn unquote(text: &str) -> String {
     let mut new_str = String::from(text);
     for &c in ['"', '\''].iter() {
@@ -502,7 +568,7 @@ pub fn unquote(text: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::cmd_to_tokens;
-    use super::line_to_cmds;
+    use super::cmd_to_with_redirects;
     use super::line_to_plain_tokens;
     use super::Tokens;
 
@@ -526,8 +592,35 @@ mod tests {
     fn test_line_to_tokens() {
         let v = vec![
             ("ls", vec![("", "ls")]),
-            ("(ls)", vec![("", "ls")]),
-            ("(ls -lh)", vec![("", "ls"), ("", "-lh")]),
+            ("(ls)", vec![("(", "ls")]),
+            ("(ls -lh)", vec![("(", "ls -lh")]),
+            (
+                "(cd foo && echo \"a)\" | wc) > bar",
+                vec![("(", "cd foo && echo \"a)\" | wc"), ("", ">"), ("", "bar")],
+            ),
+            ("ls | (cat)", vec![("", "ls"), ("", "|"), ("(", "cat")]),
+            (
+                "cat <<EOF\nfoo $x\nEOF\n | wc",
+                vec![
+                    ("", "cat"),
+                    ("", "<<"),
+                    ("\"", "foo $x\n"),
+                    ("", "|"),
+                    ("", "wc"),
+                ],
+            ),
+            (
+                "cat <<-'EOF'\n\tfoo $x\n\tEOF\n",
+                vec![("", "cat"), ("", "<<"), ("'", "foo $x\n")],
+            ),
+            (
+                "cat <<<\"a b\"",
+                vec![("", "cat"), ("", "<<<"), ("\"", "a b")],
+            ),
+            (
+                "(cat <<EOF\nit's\nEOF\n)",
+                vec![("(", "cat <<EOF\nit's\nEOF\n")],
+            ),
             ("  ls   ", vec![("", "ls")]),
             ("ls ' a '", vec![("", "ls"), ("'", " a ")]),
             ("ls -lh", vec![("", "ls"), ("", "-lh")]),
@@ -578,6 +671,42 @@ mod tests {
                 vec![("", "export"), ("", "FOO=\"`date` and `go version`\"")],
             ),
             ("ps|wc", vec![("", "ps"), ("", "|"), ("", "wc")]),
+            ("make |& tee", vec![("", "make"), ("", "|&"), ("", "tee")]),
+            (
+                "echo ${foo:-a b} c",
+                vec![("", "echo"), ("", "${foo:-a b}"), ("", "c")],
+            ),
+            (
+                "arr=(a \"b c\" $(ls)) map[$k]+=\"d e\"",
+                vec![("", "arr=(a \"b c\" $(ls))"), ("", "map[$k]+=\"d e\"")],
+            ),
+            ("re='^(a|b) c$'", vec![("", "re='^(a|b) c$'")]),
+            (
+                "diff <(sort a) <(ls -l (foo))",
+                vec![("", "diff"), ("<(", "sort a"), ("<(", "ls -l (foo)")],
+            ),
+            (
+                "tee >(gzip > out.gz)",
+                vec![("", "tee"), (">(", "gzip > out.gz")],
+            ),
+            (
+                "echo $(dirname $(which ls)) \"$(echo \")\" 'a  b')\"",
+                vec![
+                    ("", "echo"),
+                    ("", "$(dirname $(which ls))"),
+                    ("\"", "$(echo \")\" 'a  b')"),
+                ],
+            ),
+            (
+                "echo a$(echo \"(\")b '$(x' \\$(y)",
+                vec![
+                    ("", "echo"),
+                    ("", "a$(echo \"(\")b"),
+                    ("'", "$(x"),
+                    ("\\", "$(y)"),
+                ],
+            ),
+            ("make|&tee", vec![("", "make"), ("", "|&"), ("", "tee")]),
             (
                 "cat foo.txt|sort -n|wc",
                 vec![
@@ -631,6 +760,18 @@ mod tests {
                 "ls > /dev/null 2>&1",
                 vec![("", "ls"), ("", ">"), ("", "/dev/null"), ("", "2>&1")],
             ),
+            (
+                "echo a >| foo | wc",
+                vec![
+                    ("", "echo"),
+                    ("", "a"),
+                    ("", ">|"),
+                    ("", "foo"),
+                    ("", "|"),
+                    ("", "wc"),
+                ],
+            ),
+            ("echo a>|foo", vec![("", "echo"), ("", "a>|foo")]),
             (
                 "ls > /dev/null 2>& 1",
                 vec![
@@ -743,6 +884,70 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cmd_to_with_redirects() {
+        let v = vec![
+            ("ls > foo", vec!["ls"], vec![("1", ">", "foo")]),
+            ("echo foo>bar", vec!["echo", "foo"], vec![("1", ">", "bar")]),
+            (
+                "echo foo >| bar",
+                vec!["echo", "foo"],
+                vec![("1", ">|", "bar")],
+            ),
+            (
+                "ls 2>&1 >> 'a b'",
+                vec!["ls"],
+                vec![("2", ">", "&1"), ("1", ">>", "a b")],
+            ),
+            (
+                "foo 3> log 4< in 5<>rw 6>&- <&4",
+                vec!["foo"],
+                vec![
+                    ("3", ">", "log"),
+                    ("4", "<", "in"),
+                    ("5", "<>", "rw"),
+                    ("6", ">", "&-"),
+                    ("0", "<", "&4"),
+                ],
+            ),
+            ("cat < foo -", vec!["cat", "-"], vec![("0", "<", "foo")]),
+            (
+                "make &> build.log",
+                vec!["make"],
+                vec![("1", ">", "build.log"), ("2", ">", "&1")],
+            ),
+            (
+                "echo 2&>>log",
+                vec!["echo", "2"],
+                vec![("1", ">>", "log"), ("2", ">", "&1")],
+            ),
+        ];
+        for (line, args, redirects) in v {
+            println!("\ninput: {:?}", line);
+            let cmd = cmd_to_with_redirects(&cmd_to_tokens(line)).unwrap();
+            let real: Vec<&str> = cmd.tokens.iter().map(|x| x.1.as_str()).collect();
+            assert_eq!(real, args);
+            let real: Vec<(&str, &str, &str)> = cmd
+                .redirects
+                .iter()
+                .map(|x| (x.0.as_str(), x.1.as_str(), x.2.as_str()))
+                .collect();
+            assert_eq!(real, redirects);
+        }
+
+        for line in vec![
+            "ls >",
+            "ls 2>&foo",
+            "ls >>&1",
+            "ls > &1",
+            "cat <",
+            "ls &>&2",
+        ] {
+            println!("\ninput: {:?}", line);
+            assert!(cmd_to_with_redirects(&cmd_to_tokens(line)).is_err());
+        }
+    }
+
     #[test]
     fn test_parse_line() {
         let v = vec![
@@ -792,47 +997,4 @@ mod tests {
             _assert_vec_str_eq(real, right);
         }
     }
-
-    #[test]
-    fn test_line_to_cmds() {
-        let v = vec![
-            ("ls", vec!["ls"]),
-            ("ls &", vec!["ls &"]),
-            ("ls -lh", vec!["ls -lh"]),
-            (
-                "awk -F \" \" '{print $1}' README.md",
-                vec!["awk -F \" \" '{print $1}' README.md"],
-            ),
-            ("ls | wc", vec!["ls | wc"]),
-            ("echo #foo; echo bar", vec!["echo"]),
-            ("echo foo; echo bar", vec!["echo foo", ";", "echo bar"]),
-            ("echo 'foo; echo bar'", vec!["echo 'foo; echo bar'"]),
-            ("echo \"foo; echo bar\"", vec!["echo \"foo; echo bar\""]),
-            ("echo `foo; echo bar`", vec!["echo `foo; echo bar`"]),
-            ("echo foo && echo bar", vec!["echo foo", "&&", "echo bar"]),
-            (
-                "echo foo && echo bar && echo baz",
-                vec!["echo foo", "&&", "echo bar", "&&", "echo baz"],
-            ),
-            ("echo foo || echo bar", vec!["echo foo", "||", "echo bar"]),
-            (
-                "echo foo && echo bar; echo end",
-                vec!["echo foo", "&&", "echo bar", ";", "echo end"],
-            ),
-            ("echo \"\\\"\"", vec!["echo \"\\\"\""]),
-            (
-                "man awk| awk -F \"[ ,.\\\"]+\" 'foo' |sort -k2nr|head",
-                vec!["man awk| awk -F \"[ ,.\\\"]+\" 'foo' |sort -k2nr|head"],
-            ),
-            (";", vec![";"]),
-            ("||", vec!["||"]),
-            ("&&", vec!["&&"]),
-            ("ls foo\\#bar", vec!["ls foo\\#bar"]),
-            ("ls \\|\\|foo", vec!["ls \\|\\|foo"]),
-        ];
-
-        for (left, right) in v {
-            _assert_vec_str_eq(line_to_cmds(left), right);
-        }
-    }
 }
@@ -0,0 +1,1464 @@
+use std::fmt;
+
+use crate::tools;
+use crate::types::{Node, Stmt};
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// The text is valid so far, but needs more lines to be complete,
+    /// e.g. an `if` without `fi`.
+    Incomplete(String),
+    Syntax(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Incomplete(msg) => write!(f, "syntax error: {}", msg),
+            ParseError::Syntax(msg) => write!(f, "syntax error: {}", msg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Op(String),
+}
+
+/// Words that end a command list, they cannot start a command.
+const LIST_ENDS: [&str; 8] = ["then", "elif", "else", "fi", "do", "done", "esac", "}"];
+
+/// Operators that end an item of `case`.
+const CASE_ENDS: [&str; 3] = [";;", ";&", ";;&"];
+
+fn unexpected_eof(what: &str) -> ParseError {
+    ParseError::Incomplete(format!(
+        "unexpected end of file while looking for `{}'",
+        what
+    ))
+}
+
+fn unexpected_token(token: &str) -> ParseError {
+    let token = if token == "\n" { "newline" } else { token };
+    ParseError::Syntax(format!("near unexpected token `{}'", token))
+}
+
+/// Returns the index right after the regex of `=~` starting at `start`,
+/// which ends at a whitespace out of parentheses.
+fn scan_regex(chars: &[char], start: usize) -> Result<usize, ParseError> {
+    let len = chars.len();
+    let mut depth = 0;
+    let mut i = start;
+    while i < len {
+        let c = chars[i];
+        if c == '\\' {
+            i += 2;
+            continue;
+        }
+        if c == '\'' || c == '"' {
+            i = scan_quoted(chars, i)?;
+            continue;
+        }
+        if c == '$' && i + 1 < len && (chars[i + 1] == '(' || chars[i + 1] == '{') {
+            i = scan_dollar(chars, i)?;
+            continue;
+        }
+        if c == '(' {
+            depth += 1;
+        } else if c == ')' {
+            depth -= 1;
+        } else if c.is_whitespace() && depth <= 0 {
+            break;
+        }
+        i += 1;
+    }
+    Ok(i.min(len))
+}
+
+/// Returns the index right after the quoted string starting at `start`,
+/// e.g. `'foo'`, `"foo $(date)"` or `` `date` ``.
+fn scan_quoted(chars: &[char], start: usize) -> Result<usize, ParseError> {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && quote != '\'' {
+            i += 2;
+            continue;
+        }
+        if c == quote {
+            return Ok(i + 1);
+        }
+        if quote == '"' && c == '$' && i + 1 < chars.len() {
+            let c_next = chars[i + 1];
+            if c_next == '(' || c_next == '{' {
+                i = scan_dollar(chars, i)?;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    Err(unexpected_eof(&quote.to_string()))
+}
+
+/// Returns the index right after `$(...)` or `${...}` starting at `start`.
+fn scan_dollar(chars: &[char], start: usize) -> Result<usize, ParseError> {
+    let open = chars[start + 1];
+    if open == '(' && chars.get(start + 2) != Some(&'(') {
+        return scan_command_substitution(chars, start);
+    }
+    let close = if open == '(' { ')' } else { '}' };
+    let mut depth = 0;
+    let mut i = start + 1;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' {
+            i += 2;
+            continue;
+        }
+        if c == '\'' || c == '"' || c == '`' {
+            i = scan_quoted(chars, i)?;
+            continue;
+        }
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Ok(i + 1);
+            }
+        }
+        i += 1;
+    }
+    Err(unexpected_eof(&close.to_string()))
+}
+
+/// Returns the index right after the command substitution `$(...)`
+/// starting at `start`. It ends at the first unpaired `)` where the
+/// command in it is complete, so that the `)` of patterns like in
+/// `$(case $x in a) ...` do not count.
+pub fn scan_command_substitution(chars: &[char], start: usize) -> Result<usize, ParseError> {
+    let mut depth = 0;
+    let mut i = start + 2;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' {
+            i += 2;
+            continue;
+        }
+        if c == '\'' || c == '"' || c == '`' {
+            i = scan_quoted(chars, i)?;
+            continue;
+        }
+        if c == '$' && i + 1 < chars.len() && (chars[i + 1] == '(' || chars[i + 1] == '{') {
+            i = scan_dollar(chars, i)?;
+            continue;
+        }
+        if c == '(' {
+            depth += 1;
+        } else if c == ')' && depth > 0 {
+            depth -= 1;
+        } else if c == ')' {
+            let cmd: String = chars[start + 2..i].iter().collect();
+            match parse_script(&cmd) {
+                Err(ParseError::Incomplete(_)) => {}
+                _ => return Ok(i + 1),
+            }
+        }
+        i += 1;
+    }
+    Err(unexpected_eof(")"))
+}
+
+/// Returns the index right after `((...))` starting at `start`.
+fn scan_double_parens(chars: &[char], start: usize) -> Result<usize, ParseError> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        if chars[i] == '(' {
+            depth += 1;
+        } else if chars[i] == ')' {
+            depth -= 1;
+            if depth == 0 {
+                return Ok(i + 1);
+            }
+        }
+        i += 1;
+    }
+    Err(unexpected_eof("))"))
+}
+
+/// Returns the index right after the delimiter of a here-document
+/// starting at `start`, e.g. `EOF` or `'EOF'` in `cat <<'EOF'`.
+fn scan_heredoc_delimiter(chars: &[char], start: usize) -> Result<usize, ParseError> {
+    let mut i = start;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\'' || c == '"' {
+            i = scan_quoted(chars, i)?;
+            continue;
+        }
+        if c == '\\' {
+            i += 2;
+            continue;
+        }
+        if " \t\n;&|<>()".contains(c) {
+            break;
+        }
+        i += 1;
+    }
+    Ok(i.min(chars.len()))
+}
+
+/// Read bodies of the pending here-documents from the lines starting at
+/// `start`, and keep each of them in the word of its operator, e.g.
+/// `<<EOF\nfoo\nEOF\n`. Returns the index right after the last body.
+fn read_heredocs(
+    chars: &[char],
+    start: usize,
+    heredocs: &mut Vec<(usize, String, bool)>,
+    tokens: &mut [Token],
+) -> Result<usize, ParseError> {
+    let mut i = start;
+    for (idx, delimiter, strip_tabs) in heredocs.drain(..) {
+        let mut body = String::new();
+        loop {
+            if i >= chars.len() {
+                return Err(unexpected_eof(&delimiter));
+            }
+            let end = chars[i..]
+                .iter()
+                .position(|&c| c == '\n')
+                .map_or(chars.len(), |x| i + x);
+            let line: String = chars[i..end].iter().collect();
+            i = end + 1;
+            let line = if strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line.as_str()
+            };
+            if line == delimiter {
+                break;
+            }
+            body.push_str(line);
+            body.push('\n');
+        }
+        if let Some(Token::Word(word)) = tokens.get_mut(idx) {
+            word.push('\n');
+            word.push_str(&body);
+            word.push_str(&delimiter);
+            word.push('\n');
+        }
+    }
+    Ok(i.min(chars.len()))
+}
+
+/// Split script text into words and operators. Words are kept as they
+/// are in the text (with quotes etc.), comments are dropped.
+fn tokenize(text: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = text.chars().collect();
+    let len = chars.len();
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    // here-documents waiting for their bodies: (index of the token,
+    // delimiter, whether to strip leading tabs)
+    let mut heredocs = Vec::new();
+    let mut i = 0;
+    while i < len {
+        let c = chars[i];
+        let c_next = if i + 1 < len { chars[i + 1] } else { '\0' };
+
+        // the regex of `[[ $x =~ ^(a|b)+$ ]]` is kept in one word, with
+        // its parentheses and `|`
+        let after_regex_op = matches!(tokens.last(), Some(Token::Word(x)) if x == "=~");
+        if after_regex_op && word.is_empty() && !c.is_whitespace() {
+            let end = scan_regex(&chars, i)?;
+            tokens.push(Token::Word(chars[i..end].iter().collect()));
+            i = end;
+            continue;
+        }
+
+        if c == '\\' {
+            if c_next == '\n' {
+                // line continuation
+                i += 2;
+                continue;
+            }
+            word.push(c);
+            if i + 1 < len {
+                word.push(c_next);
+            }
+            i += 2;
+            continue;
+        }
+
+        let is_dollar = c == '$' && (c_next == '(' || c_next == '{');
+        // process substitutions like `<(ls)` are kept in one word
+        let is_proc_sub = (c == '<' || c == '>') && c_next == '(';
+        if c == '\'' || c == '"' || c == '`' || is_dollar || is_proc_sub {
+            let end = if is_dollar || is_proc_sub {
+                scan_dollar(&chars, i)?
+            } else {
+                scan_quoted(&chars, i)?
+            };
+            word.extend(chars[i..end].iter());
+            i = end;
+            continue;
+        }
+
+        // arrays like `arr=(a b c)`
+        if c == '(' && tools::re_contains(&word, r"^[a-zA-Z_][a-zA-Z0-9_]*\+?=$") {
+            let end = scan_dollar(&chars, i - 1)?;
+            word.extend(chars[i..end].iter());
+            i = end;
+            continue;
+        }
+
+        // arithmetic like `(( i++ ))` and `for ((i = 0; i < 3; i++))`
+        if c == '(' && c_next == '(' && word.is_empty() {
+            let end = scan_double_parens(&chars, i)?;
+            word.extend(chars[i..end].iter());
+            i = end;
+            continue;
+        }
+
+        // here-documents like `cat <<EOF`, their bodies are read after
+        // the end of the line, see `read_heredocs()`
+        let is_heredoc = c == '<' && c_next == '<' && !word.ends_with('<');
+        if is_heredoc && (i + 2 >= len || chars[i + 2] != '<') {
+            if !word.is_empty() {
+                tokens.push(Token::Word(word));
+                word = String::new();
+            }
+            let mut j = i + 2;
+            let strip_tabs = j < len && chars[j] == '-';
+            if strip_tabs {
+                j += 1;
+            }
+            while j < len && (chars[j] == ' ' || chars[j] == '\t') {
+                j += 1;
+            }
+            let end = scan_heredoc_delimiter(&chars, j)?;
+            if end == j {
+                let token = if j < len { chars[j] } else { '\n' };
+                return Err(unexpected_token(&token.to_string()));
+            }
+            let spec: String = chars[j..end].iter().collect();
+            let delimiter: String = spec
+                .chars()
+                .filter(|&x| x != '\'' && x != '"' && x != '\\')
+                .collect();
+            let op = if strip_tabs { "<<-" } else { "<<" };
+            tokens.push(Token::Word(format!("{}{}", op, spec)));
+            heredocs.push((tokens.len() - 1, delimiter, strip_tabs));
+            i = end;
+            continue;
+        }
+
+        // keep `()` of function definitions like `foo() { ... }` in words,
+        // which end there for cases like `foo(){ ...; }`
+        if c == '(' && c_next == ')' {
+            word.push_str("()");
+            tokens.push(Token::Word(word));
+            word = String::new();
+            i += 2;
+            continue;
+        }
+
+        if c == '#' && word.is_empty() {
+            while i < len && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+
+        // keep redirections like `2>&1`, `&>foo` and `>|foo` in one word
+        let is_clobber = c == '|' && word.ends_with('>');
+        if is_clobber || (c == '&' && (word.ends_with('>') || word.ends_with('<') || c_next == '>'))
+        {
+            word.push(c);
+            i += 1;
+            continue;
+        }
+
+        let is_parenthesis = c == ')' || (c == '(' && word.is_empty());
+        if c == ' ' || c == '\t' || c == '\n' || c == ';' || c == '&' || c == '|' || is_parenthesis
+        {
+            if !word.is_empty() {
+                tokens.push(Token::Word(word));
+                word = String::new();
+            }
+            if c == ' ' || c == '\t' {
+                i += 1;
+                continue;
+            }
+
+            let op = if (c == '&' || c == '|') && c_next == c {
+                i += 1;
+                format!("{}{}", c, c)
+            } else if c == '|' && c_next == '&' {
+                i += 1;
+                String::from("|&")
+            } else if c == ';' && c_next == '&' {
+                i += 1;
+                String::from(";&")
+            } else if c == ';' && c_next == ';' {
+                i += 1;
+                if i + 1 < len && chars[i + 1] == '&' {
+                    i += 1;
+                    String::from(";;&")
+                } else {
+                    String::from(";;")
+                }
+            } else {
+                c.to_string()
+            };
+            tokens.push(Token::Op(op));
+            i += 1;
+            if c == '\n' && !heredocs.is_empty() {
+                i = read_heredocs(&chars, i, &mut heredocs, &mut tokens)?;
+                // commands are kept like `cat <<EOF\nfoo\nEOF\n | wc -l`,
+                // where the rest of the command follows the body
+                let mut j = i;
+                while j < len && (chars[j] == ' ' || chars[j] == '\t') {
+                    j += 1;
+                }
+                if j < len && "|&;)".contains(chars[j]) {
+                    tokens.pop();
+                }
+            }
+            continue;
+        }
+
+        word.push(c);
+        i += 1;
+    }
+    if !word.is_empty() {
+        tokens.push(Token::Word(word));
+    }
+    if let Some((_, delimiter, _)) = heredocs.first() {
+        return Err(unexpected_eof(delimiter));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(x)) => Some(x.as_str()),
+            _ => None,
+        }
+    }
+
+    fn peek_word_at(&self, offset: usize) -> Option<&str> {
+        match self.tokens.get(self.pos + offset) {
+            Some(Token::Word(x)) => Some(x.as_str()),
+            _ => None,
+        }
+    }
+
+    fn peek_op(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Op(x)) => Some(x.as_str()),
+            _ => None,
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek_op() == Some("\n") {
+            self.pos += 1;
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(Token::Word(x)) if x == word => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(Token::Word(x)) | Some(Token::Op(x)) => Err(unexpected_token(x)),
+            None => Err(unexpected_eof(word)),
+        }
+    }
+
+    /// Parse commands until one of the `ends` words is met at the place
+    /// of a command. The end word is not consumed.
+    fn parse_list(&mut self, ends: &[&str]) -> Result<Vec<Stmt>, ParseError> {
+        let mut stmts = Vec::new();
+        loop {
+            while self.peek_op() == Some("\n") || self.peek_op() == Some(";") {
+                self.pos += 1;
+            }
+            match self.peek() {
+                None => {
+                    if ends.is_empty() {
+                        return Ok(stmts);
+                    }
+                    return Err(unexpected_eof(ends[ends.len() - 1]));
+                }
+                Some(Token::Word(x)) => {
+                    if ends.contains(&x.as_str()) {
+                        return Ok(stmts);
+                    }
+                }
+                Some(Token::Op(x)) => {
+                    if ends.contains(&"esac") && CASE_ENDS.contains(&x.as_str()) {
+                        return Ok(stmts);
+                    }
+                    if ends.contains(&")") && x == ")" {
+                        return Ok(stmts);
+                    }
+                    if x != "(" {
+                        return Err(unexpected_token(x));
+                    }
+                }
+            }
+            self.parse_and_or(&mut stmts)?;
+        }
+    }
+
+    /// Parse commands joined with `&&` and `||`, and the separator
+    /// (if any) after them.
+    fn parse_and_or(&mut self, stmts: &mut Vec<Stmt>) -> Result<(), ParseError> {
+        let mut sep = String::from(";");
+        loop {
+            let mut negate = false;
+            if self.peek_word() == Some("!") {
+                negate = true;
+                self.pos += 1;
+            }
+            let (mut node, text) = self.parse_pipeline()?;
+
+            let op = match self.peek() {
+                None => String::new(),
+                Some(Token::Op(x)) => x.clone(),
+                Some(Token::Word(x)) => {
+                    return Err(unexpected_token(x));
+                }
+            };
+            if op == "&" {
+                match node {
+                    Node::Cmd(ref mut text) => {
+                        // `run_proc()` takes care of background commands
+                        text.push_str(" &");
+                    }
+                    _ => {
+                        // run it in a sub-process, like `( ... ) &`
+                        node = Node::Cmd(format!("( {} ) &", text));
+                    }
+                }
+            }
+            stmts.push(Stmt { sep, negate, node });
+
+            if op == "&&" || op == "||" {
+                self.pos += 1;
+                self.skip_newlines();
+                if self.peek().is_none() {
+                    return Err(unexpected_eof("command"));
+                }
+                sep = op;
+                continue;
+            }
+            if op == "(" {
+                return Err(unexpected_token(&op));
+            }
+            // `;;` etc. are left for `parse_case()`, `)` for `parse_subshell()`
+            if !op.is_empty() && op != ")" && !CASE_ENDS.contains(&op.as_str()) {
+                self.pos += 1;
+            }
+            return Ok(());
+        }
+    }
+
+    /// Parse a command, or a pipeline of them. Also returns the text of
+    /// the command, so compound commands can run in sub-processes.
+    fn parse_pipeline(&mut self) -> Result<(Node, String), ParseError> {
+        let mut pipeline = String::new();
+        loop {
+            let start = self.pos;
+            let node = self.parse_command()?;
+            let text = self.text_of(start, self.pos);
+            let redirects = self.parse_redirects()?;
+
+            let pipe = match self.peek_op() {
+                Some(x) if x == "|" || x == "|&" => x.to_string(),
+                _ => String::new(),
+            };
+            let is_pipe = !pipe.is_empty();
+            if pipeline.is_empty() && !is_pipe {
+                if redirects.is_empty() {
+                    return Ok((node, text));
+                }
+                let text_all = format!("{} {}", text, redirects);
+                let node = match node {
+                    // for subshells like `(cd foo && make) > make.log`
+                    Node::Cmd(_) => Node::Cmd(text_all.clone()),
+                    _ => Node::Redirect(Box::new(node), redirects),
+                };
+                return Ok((node, text_all));
+            }
+
+            // compound commands in pipelines run in sub-processes
+            let mut stage = match node {
+                Node::Cmd(x) => x,
+                _ => format!("( {} )", text),
+            };
+            if !redirects.is_empty() {
+                stage.push(' ');
+                stage.push_str(&redirects);
+            }
+            pipeline.push_str(&stage);
+            if !is_pipe {
+                break;
+            }
+            pipeline.push_str(&format!(" {} ", pipe));
+
+            self.pos += 1;
+            self.skip_newlines();
+            match self.peek() {
+                Some(Token::Word(_)) => {}
+                Some(Token::Op(x)) if x == "(" => {}
+                Some(Token::Op(x)) => return Err(unexpected_token(x)),
+                None => return Err(unexpected_eof("command")),
+            }
+        }
+        Ok((Node::Cmd(pipeline.clone()), pipeline))
+    }
+
+    /// The script text of tokens from `start` to `end` (not included).
+    fn text_of(&self, start: usize, end: usize) -> String {
+        let words: Vec<&str> = self.tokens[start..end]
+            .iter()
+            .map(|x| match x {
+                Token::Word(x) | Token::Op(x) => x.as_str(),
+            })
+            .collect();
+        words.join(" ")
+    }
+
+    /// Parse redirections after compound commands, e.g. `> foo.log 2>&1`.
+    fn parse_redirects(&mut self) -> Result<String, ParseError> {
+        let mut redirects = Vec::new();
+        while let Some(word) = self.peek_word() {
+            if !tools::re_contains(word, r"^([0-9]*|&)(>|<)") {
+                break;
+            }
+            let word = word.to_string();
+            self.pos += 1;
+            // the file name is in next word for cases like `> foo.log`
+            if tools::re_contains(&word, r"^([0-9]*(>>?|>\||<|<>|<<<)|&>>?)$") {
+                match self.peek() {
+                    Some(Token::Word(x)) => {
+                        redirects.push(word);
+                        redirects.push(x.clone());
+                        self.pos += 1;
+                        continue;
+                    }
+                    Some(Token::Op(x)) => return Err(unexpected_token(x)),
+                    None => return Err(unexpected_eof("file name")),
+                }
+            }
+            redirects.push(word);
+        }
+        Ok(redirects.join(" "))
+    }
+
+    fn parse_command(&mut self) -> Result<Node, ParseError> {
+        let word = match self.peek() {
+            Some(Token::Word(x)) => x.clone(),
+            Some(Token::Op(x)) if x == "(" => {
+                return self.parse_subshell();
+            }
+            Some(Token::Op(x)) => {
+                return Err(unexpected_token(x));
+            }
+            None => {
+                return Err(unexpected_eof("command"));
+            }
+        };
+        if LIST_ENDS.contains(&word.as_str()) {
+            return Err(unexpected_token(&word));
+        }
+        if word == "if" {
+            return self.parse_if();
+        }
+        if word == "{" {
+            return self.parse_group();
+        }
+        if word == "for" {
+            return self.parse_for();
+        }
+        if word == "while" || word == "until" {
+            return self.parse_while();
+        }
+        if word == "case" {
+            return self.parse_case();
+        }
+        if word == "[[" {
+            return self.parse_cond();
+        }
+        if word.starts_with("((") && word.ends_with("))") {
+            self.pos += 1;
+            return Ok(Node::Arith(word[2..word.len() - 2].to_string()));
+        }
+        // not for empty arrays like `arr=()`
+        let is_func = word.ends_with("()") && !word.contains('=');
+        if word == "function" || is_func || self.peek_word_at(1) == Some("()") {
+            return self.parse_function();
+        }
+        self.parse_simple()
+    }
+
+    /// Parse `[[ ... ]]`, its words are kept as they are, and expanded
+    /// when it runs, see `builtins::test::run_cond()`.
+    fn parse_cond(&mut self) -> Result<Node, ParseError> {
+        self.expect_word("[[")?;
+        let mut words = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Word(x)) if x == "]]" => break,
+                Some(Token::Word(x)) => words.push(x.clone()),
+                Some(Token::Op(x)) if x == "\n" => {}
+                Some(Token::Op(x)) if ["&&", "||", "(", ")"].contains(&x.as_str()) => {
+                    words.push(x.clone());
+                }
+                Some(Token::Op(x)) => return Err(unexpected_token(x)),
+                None => return Err(unexpected_eof("]]")),
+            }
+            self.pos += 1;
+        }
+        if words.is_empty() {
+            return Err(unexpected_token("]]"));
+        }
+        self.pos += 1;
+        Ok(Node::Cond(words))
+    }
+
+    fn parse_simple(&mut self) -> Result<Node, ParseError> {
+        let mut words = Vec::new();
+        while let Some(x) = self.peek_word() {
+            words.push(x.to_string());
+            self.pos += 1;
+        }
+        Ok(Node::Cmd(words.join(" ")))
+    }
+
+    /// Parse `( ... )`, it runs in a sub-process via `run_proc()`.
+    fn parse_subshell(&mut self) -> Result<Node, ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+        let body = self.parse_list(&[")"])?;
+        if body.is_empty() {
+            return Err(unexpected_token(")"));
+        }
+        self.pos += 1;
+        Ok(Node::Cmd(self.text_of(start, self.pos)))
+    }
+
+    fn parse_group(&mut self) -> Result<Node, ParseError> {
+        self.expect_word("{")?;
+        let body = self.parse_list(&["}"])?;
+        if body.is_empty() {
+            return Err(unexpected_token("}"));
+        }
+        self.expect_word("}")?;
+        Ok(Node::Group(body))
+    }
+
+    fn parse_if(&mut self) -> Result<Node, ParseError> {
+        self.expect_word("if")?;
+        let mut branches = Vec::new();
+        let mut else_body = Vec::new();
+        loop {
+            let cond = self.parse_list(&["then"])?;
+            if cond.is_empty() {
+                return Err(unexpected_token("then"));
+            }
+            self.expect_word("then")?;
+            let body = self.parse_list(&["elif", "else", "fi"])?;
+            if body.is_empty() {
+                let token = self.peek_word().unwrap_or("fi").to_string();
+                return Err(unexpected_token(&token));
+            }
+            branches.push((cond, body));
+
+            let word = self.peek_word().unwrap_or("").to_string();
+            self.pos += 1;
+            if word == "elif" {
+                continue;
+            }
+            if word == "else" {
+                else_body = self.parse_list(&["fi"])?;
+                if else_body.is_empty() {
+                    return Err(unexpected_token("fi"));
+                }
+                self.expect_word("fi")?;
+            }
+            break;
+        }
+        Ok(Node::If(branches, else_body))
+    }
+
+    /// Parse the `do ...; done` part of loops.
+    fn parse_do_group(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect_word("do")?;
+        let body = self.parse_list(&["done"])?;
+        if body.is_empty() {
+            return Err(unexpected_token("done"));
+        }
+        self.expect_word("done")?;
+        Ok(body)
+    }
+
+    fn parse_for(&mut self) -> Result<Node, ParseError> {
+        self.expect_word("for")?;
+        let name = match self.peek() {
+            Some(Token::Word(x)) => x.clone(),
+            Some(Token::Op(x)) => return Err(unexpected_token(x)),
+            None => return Err(unexpected_eof("do")),
+        };
+        self.pos += 1;
+
+        if name.starts_with("((") && name.ends_with("))") {
+            let exprs: Vec<&str> = name[2..name.len() - 2].split(';').collect();
+            if exprs.len() != 3 {
+                return Err(ParseError::Syntax(format!(
+                    "invalid arithmetic for loop: `{}'",
+                    name
+                )));
+            }
+            while self.peek_op() == Some(";") || self.peek_op() == Some("\n") {
+                self.pos += 1;
+            }
+            let body = self.parse_do_group()?;
+            return Ok(Node::ForArith(
+                exprs[0].trim().to_string(),
+                exprs[1].trim().to_string(),
+                exprs[2].trim().to_string(),
+                body,
+            ));
+        }
+
+        if !tools::re_contains(&name, r"^[a-zA-Z_][a-zA-Z0-9_]*$") {
+            return Err(ParseError::Syntax(format!(
+                "`{}': not a valid identifier",
+                name
+            )));
+        }
+
+        self.skip_newlines();
+        let mut words = None;
+        if self.peek_word() == Some("in") {
+            self.pos += 1;
+            let mut list = Vec::new();
+            while let Some(Token::Word(x)) = self.peek() {
+                list.push(x.clone());
+                self.pos += 1;
+            }
+            words = Some(list.join(" "));
+        }
+        while self.peek_op() == Some(";") || self.peek_op() == Some("\n") {
+            self.pos += 1;
+        }
+        let body = self.parse_do_group()?;
+        Ok(Node::For(name, words, body))
+    }
+
+    fn parse_while(&mut self) -> Result<Node, ParseError> {
+        let word = self.peek_word().unwrap_or("").to_string();
+        self.pos += 1;
+        let cond = self.parse_list(&["do"])?;
+        if cond.is_empty() {
+            return Err(unexpected_token("do"));
+        }
+        let body = self.parse_do_group()?;
+        if word == "until" {
+            Ok(Node::Until(cond, body))
+        } else {
+            Ok(Node::While(cond, body))
+        }
+    }
+
+    /// Parse `foo() { ... }` or `function foo { ... }`.
+    fn parse_function(&mut self) -> Result<Node, ParseError> {
+        if self.peek_word() == Some("function") {
+            self.pos += 1;
+        }
+        let mut name = match self.peek() {
+            Some(Token::Word(x)) => x.clone(),
+            Some(Token::Op(x)) => return Err(unexpected_token(x)),
+            None => return Err(unexpected_eof("{")),
+        };
+        self.pos += 1;
+        if name.ends_with("()") {
+            name.truncate(name.len() - 2);
+        } else if self.peek_word() == Some("()") {
+            self.pos += 1;
+        }
+        if !tools::re_contains(&name, r"^[a-zA-Z0-9_\.-]+$") {
+            return Err(ParseError::Syntax(format!(
+                "`{}': not a valid function name",
+                name
+            )));
+        }
+
+        self.skip_newlines();
+        self.expect_word("{")?;
+        let body = self.parse_list(&["}"])?;
+        if body.is_empty() {
+            return Err(unexpected_token("}"));
+        }
+        self.expect_word("}")?;
+        Ok(Node::FuncDef(name, body))
+    }
+
+    fn parse_case(&mut self) -> Result<Node, ParseError> {
+        self.expect_word("case")?;
+        let word = match self.peek() {
+            Some(Token::Word(x)) => x.clone(),
+            Some(Token::Op(x)) => return Err(unexpected_token(x)),
+            None => return Err(unexpected_eof("in")),
+        };
+        self.pos += 1;
+        self.skip_newlines();
+        self.expect_word("in")?;
+
+        let mut items = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.peek_word() == Some("esac") {
+                self.pos += 1;
+                break;
+            }
+            let patterns = self.parse_case_patterns()?;
+            let body = self.parse_list(&["esac"])?;
+            // `;;` can be omitted for the last item
+            let mut end = String::from(";;");
+            if let Some(x) = self.peek_op() {
+                end = x.to_string();
+                self.pos += 1;
+            }
+            items.push((patterns, body, end));
+        }
+        Ok(Node::Case(word, items))
+    }
+
+    /// Parse patterns of a `case` item, e.g. `foo|bar*)` or `(foo)`.
+    fn parse_case_patterns(&mut self) -> Result<Vec<String>, ParseError> {
+        if self.peek_op() == Some("(") {
+            self.pos += 1;
+        }
+        let mut patterns = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Word(x)) => patterns.push(x.clone()),
+                Some(Token::Op(x)) => return Err(unexpected_token(x)),
+                None => return Err(unexpected_eof("esac")),
+            }
+            self.pos += 1;
+            match self.peek() {
+                Some(Token::Op(x)) if x == "|" => self.pos += 1,
+                Some(Token::Op(x)) if x == ")" => {
+                    self.pos += 1;
+                    return Ok(patterns);
+                }
+                Some(Token::Word(x)) | Some(Token::Op(x)) => return Err(unexpected_token(x)),
+                None => return Err(unexpected_eof("esac")),
+            }
+        }
+    }
+}
+
+/// Parse script text (may have multiple lines) into commands. e.g.
+/// >>> parse_script("echo foo && echo bar; echo end");
+/// Ok(vec![
+///     Stmt { sep: ";", negate: false, node: Cmd("echo foo") },
+///     Stmt { sep: "&&", negate: false, node: Cmd("echo bar") },
+///     Stmt { sep: ";", negate: false, node: Cmd("echo end") },
+/// ])
+pub fn parse_script(text: &str) -> Result<Vec<Stmt>, ParseError> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    parser.parse_list(&[])
+}
+
+/// Is the script text complete, or does it need more lines?
+pub fn is_complete(text: &str) -> bool {
+    !matches!(parse_script(text), Err(ParseError::Incomplete(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_complete;
+    use super::parse_script;
+    use super::ParseError;
+    use crate::types::{Node, Stmt};
+
+    fn cmd(sep: &str, text: &str) -> Stmt {
+        Stmt {
+            sep: sep.to_string(),
+            negate: false,
+            node: Node::Cmd(text.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_parse_cmds() {
+        let v = vec![
+            ("ls", vec![cmd(";", "ls")]),
+            ("ls &", vec![cmd(";", "ls &")]),
+            ("ls -lh", vec![cmd(";", "ls -lh")]),
+            (
+                "awk -F \" \" '{print $1}' README.md",
+                vec![cmd(";", "awk -F \" \" '{print $1}' README.md")],
+            ),
+            ("ls | wc", vec![cmd(";", "ls | wc")]),
+            ("echo #foo; echo bar", vec![cmd(";", "echo")]),
+            (
+                "echo foo; echo bar",
+                vec![cmd(";", "echo foo"), cmd(";", "echo bar")],
+            ),
+            (
+                "echo 'foo; echo bar'",
+                vec![cmd(";", "echo 'foo; echo bar'")],
+            ),
+            (
+                "echo \"foo; echo bar\"",
+                vec![cmd(";", "echo \"foo; echo bar\"")],
+            ),
+            (
+                "echo `foo; echo bar`",
+                vec![cmd(";", "echo `foo; echo bar`")],
+            ),
+            (
+                "echo $(foo; echo bar)",
+                vec![cmd(";", "echo $(foo; echo bar)")],
+            ),
+            (
+                "echo $(case $x in a) echo b;; esac); echo c",
+                vec![
+                    cmd(";", "echo $(case $x in a) echo b;; esac)"),
+                    cmd(";", "echo c"),
+                ],
+            ),
+            (
+                "echo foo && echo bar",
+                vec![cmd(";", "echo foo"), cmd("&&", "echo bar")],
+            ),
+            (
+                "echo foo && echo bar && echo baz",
+                vec![
+                    cmd(";", "echo foo"),
+                    cmd("&&", "echo bar"),
+                    cmd("&&", "echo baz"),
+                ],
+            ),
+            (
+                "echo foo || echo bar",
+                vec![cmd(";", "echo foo"), cmd("||", "echo bar")],
+            ),
+            (
+                "echo foo && echo bar; echo end",
+                vec![
+                    cmd(";", "echo foo"),
+                    cmd("&&", "echo bar"),
+                    cmd(";", "echo end"),
+                ],
+            ),
+            ("echo \"\\\"\"", vec![cmd(";", "echo \"\\\"\"")]),
+            (
+                "man awk| awk -F \"[ ,.\\\"]+\" 'foo' |sort -k2nr|head",
+                vec![cmd(
+                    ";",
+                    "man awk | awk -F \"[ ,.\\\"]+\" 'foo' | sort -k2nr | head",
+                )],
+            ),
+            (";", vec![]),
+            ("ls foo\\#bar", vec![cmd(";", "ls foo\\#bar")]),
+            ("ls \\|\\|foo", vec![cmd(";", "ls \\|\\|foo")]),
+            ("ls > /dev/null 2>&1", vec![cmd(";", "ls > /dev/null 2>&1")]),
+            ("make&>build.log", vec![cmd(";", "make&>build.log")]),
+            ("echo a >| b | wc", vec![cmd(";", "echo a >| b | wc")]),
+            (
+                "arr=(a 'b c'\n  d) ; arr+=() x=1",
+                vec![cmd(";", "arr=(a 'b c'\n  d)"), cmd(";", "arr+=() x=1")],
+            ),
+            (
+                "diff <(sort a; echo) <(ls)",
+                vec![cmd(";", "diff <(sort a; echo) <(ls)")],
+            ),
+            (
+                "make |& tee build.log && ls",
+                vec![cmd(";", "make |& tee build.log"), cmd("&&", "ls")],
+            ),
+            (
+                "sleep 1 & echo foo",
+                vec![cmd(";", "sleep 1 &"), cmd(";", "echo foo")],
+            ),
+            (
+                "echo foo \\\n  bar\necho baz\n",
+                vec![cmd(";", "echo foo bar"), cmd(";", "echo baz")],
+            ),
+        ];
+        for (left, right) in v {
+            println!("\ninput: {:?}", left);
+            assert_eq!(parse_script(left), Ok(right));
+        }
+    }
+
+    #[test]
+    fn test_parse_if() {
+        let expected = vec![Stmt {
+            sep: ";".to_string(),
+            negate: false,
+            node: Node::If(
+                vec![
+                    (vec![cmd(";", "[ -d foo ]")], vec![cmd(";", "echo foo")]),
+                    (
+                        vec![cmd(";", "true"), cmd("&&", "false")],
+                        vec![cmd(";", "echo bar"), cmd(";", "echo baz")],
+                    ),
+                ],
+                vec![cmd(";", "echo fi")],
+            ),
+        }];
+        let v = vec![
+            "if [ -d foo ]; then echo foo; elif true && false; then echo bar; echo baz; else echo fi; fi",
+            "if [ -d foo ]\nthen\n  echo foo\nelif true &&\n  false\nthen\n  echo bar\n  echo baz\nelse\n  echo fi\nfi\n",
+            "# check it\nif [ -d foo ]; then  # yes\n echo foo\nelif true && false; then echo bar\n echo baz\nelse echo fi; fi",
+        ];
+        for text in v {
+            println!("\ninput: {:?}", text);
+            assert_eq!(parse_script(text), Ok(expected.clone()));
+        }
+
+        let stmts = parse_script("! if true; then echo a; fi || echo b").unwrap();
+        assert_eq!(stmts.len(), 2);
+        assert!(stmts[0].negate);
+        assert_eq!(stmts[1], cmd("||", "echo b"));
+    }
+
+    #[test]
+    fn test_parse_loops() {
+        let body = vec![cmd(";", "echo $i"), cmd(";", "break")];
+        let v = vec![
+            (
+                "for i in a 'b c' $FOO; do echo $i; break; done",
+                Node::For(
+                    "i".to_string(),
+                    Some("a 'b c' $FOO".to_string()),
+                    body.clone(),
+                ),
+            ),
+            (
+                "for i\ndo\n  echo $i\n  break\ndone\n",
+                Node::For("i".to_string(), None, body.clone()),
+            ),
+            (
+                "for ((i = 0; i < 3; i++)); do echo $i; break; done",
+                Node::ForArith(
+                    "i = 0".to_string(),
+                    "i < 3".to_string(),
+                    "i++".to_string(),
+                    body.clone(),
+                ),
+            ),
+            ("(( i += 2 ))", Node::Arith(" i += 2 ".to_string())),
+            ("((i > (1 + 2)))", Node::Arith("i > (1 + 2)".to_string())),
+            (
+                "while true; do echo $i; break; done",
+                Node::While(vec![cmd(";", "true")], body.clone()),
+            ),
+            (
+                "until [ -d foo ] || false\ndo echo $i\n break\ndone",
+                Node::Until(
+                    vec![cmd(";", "[ -d foo ]"), cmd("||", "false")],
+                    body.clone(),
+                ),
+            ),
+        ];
+        for (text, node) in v {
+            println!("\ninput: {:?}", text);
+            let expected = vec![Stmt {
+                sep: ";".to_string(),
+                negate: false,
+                node,
+            }];
+            assert_eq!(parse_script(text), Ok(expected));
+        }
+
+        let stmts = parse_script("for i in a; do for j in b; do echo; done; done").unwrap();
+        match &stmts[0].node {
+            Node::For(_, _, body) => match body[0].node {
+                Node::For(..) => {}
+                _ => panic!("nested loop expected"),
+            },
+            _ => panic!("loop expected"),
+        }
+    }
+
+    #[test]
+    fn test_parse_case() {
+        let item = |patterns: Vec<&str>, body: Vec<Stmt>, end: &str| {
+            (
+                patterns
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>(),
+                body,
+                end.to_string(),
+            )
+        };
+        let expected = vec![Stmt {
+            sep: ";".to_string(),
+            negate: false,
+            node: Node::Case(
+                "$(uname)".to_string(),
+                vec![
+                    item(vec!["Darwin"], vec![cmd(";", "echo mac")], ";;"),
+                    item(
+                        vec!["Linux", "*BSD"],
+                        vec![cmd(";", "echo unix"), cmd("&&", "true")],
+                        ";&",
+                    ),
+                    item(vec!["'a b'"], vec![], ";;&"),
+                    item(vec!["*"], vec![cmd(";", "echo other")], ";;"),
+                ],
+            ),
+        }];
+        let v = vec![
+            "case $(uname) in Darwin) echo mac;; Linux|*BSD) echo unix && true;& 'a b') ;;& *) echo other; esac",
+            "case $(uname) in\n  (Darwin)\n    echo mac\n    ;;\n  Linux | *BSD )\n    echo unix &&\n      true\n    ;&\n  'a b')\n    ;;&\n  *) echo other\n    ;;\nesac\n",
+            "case $(uname)\nin Darwin)echo mac;;Linux|*BSD)echo unix && true;&'a b');;&*)echo other;;esac",
+        ];
+        for text in v {
+            println!("\ninput: {:?}", text);
+            assert_eq!(parse_script(text), Ok(expected.clone()));
+        }
+
+        let stmts = parse_script("case a in a) case b in b) echo;; esac;; esac; echo end").unwrap();
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(parse_script("case a in esac").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_functions() {
+        let body = vec![cmd(";", "echo foo $1"), cmd("&&", "return 1")];
+        let v = vec![
+            "foo() { echo foo $1 && return 1; }",
+            "foo () {\n  echo foo $1 &&\n  return 1\n}",
+            "foo()\n{\n  echo foo $1 && return 1\n}\n",
+            "function foo { echo foo $1 && return 1; }",
+            "function foo() {\n  # comment\n  echo foo $1 && return 1\n}",
+            "foo(){ echo foo $1 && return 1; }",
+            "foo (){ echo foo $1 && return 1; }",
+        ];
+        for text in v {
+            println!("\ninput: {:?}", text);
+            let expected = vec![Stmt {
+                sep: ";".to_string(),
+                negate: false,
+                node: Node::FuncDef("foo".to_string(), body.clone()),
+            }];
+            assert_eq!(parse_script(text), Ok(expected));
+        }
+
+        let stmts = parse_script("foo() { echo; }; foo; bar() { foo; }").unwrap();
+        assert_eq!(stmts.len(), 3);
+        assert_eq!(stmts[1], cmd(";", "foo"));
+    }
+
+    #[test]
+    fn test_parse_groups() {
+        let v = vec![
+            ("(cd foo && make)", "( cd foo && make )"),
+            ("(cd foo; make) > make.log", "( cd foo ; make ) > make.log"),
+            ("ls | { echo a; echo b; }", "ls | ( { echo a ; echo b ; } )"),
+            ("{ make; } |& tee log", "( { make ; } ) |& tee log"),
+            (
+                "for i in a b; do echo $i; done | wc -l",
+                "( for i in a b ; do echo $i ; done ) | wc -l",
+            ),
+        ];
+        for (text, expected) in v {
+            println!("\ninput: {:?}", text);
+            assert_eq!(parse_script(text), Ok(vec![cmd(";", expected)]));
+        }
+
+        let group = Node::Group(vec![cmd(";", "echo a"), cmd(";", "echo b")]);
+        let expected = vec![Stmt {
+            sep: ";".to_string(),
+            negate: false,
+            node: Node::Redirect(Box::new(group), "> out.log 2>&1".to_string()),
+        }];
+        assert_eq!(
+            parse_script("{ echo a; echo b; } > out.log 2>&1"),
+            Ok(expected)
+        );
+        let stmts = parse_script("(cd foo && make) &> make.log").unwrap();
+        assert_eq!(stmts, vec![cmd(";", "( cd foo && make ) &> make.log")]);
+        for redirects in &[">| out.log", "<> rw.txt", "3<> rw.txt 2>| err.log"] {
+            let text = format!("{{ echo a; }} {}", redirects);
+            let group = Node::Group(vec![cmd(";", "echo a")]);
+            let node = Node::Redirect(Box::new(group), redirects.to_string());
+            assert_eq!(parse_script(&text).unwrap()[0].node, node);
+        }
+
+        let stmts = parse_script("{ echo a; } && echo b").unwrap();
+        assert_eq!(stmts[0].node, Node::Group(vec![cmd(";", "echo a")]));
+        assert_eq!(stmts[1], cmd("&&", "echo b"));
+
+        let stmts = parse_script("while true; do sleep 1; done &").unwrap();
+        assert_eq!(
+            stmts,
+            vec![cmd(";", "( while true ; do sleep 1 ; done ) &")]
+        );
+    }
+
+    #[test]
+    fn test_parse_cond() {
+        let v = vec![
+            ("[[ -f $x ]]", vec!["-f", "$x"]),
+            (
+                "[[ -n \"$a b\" && ( $y == a* || $y < b ) ]]",
+                vec![
+                    "-n", "\"$a b\"", "&&", "(", "$y", "==", "a*", "||", "$y", "<", "b", ")",
+                ],
+            ),
+            (
+                "[[ ! -d foo ||\n -d bar ]]",
+                vec!["!", "-d", "foo", "||", "-d", "bar"],
+            ),
+            (
+                "[[ $x =~ ^(a|b c)+\\ $ ]]",
+                vec!["$x", "=~", "^(a|b c)+\\ $"],
+            ),
+            ("[[ $x =~ \"(\"|x$y ]]", vec!["$x", "=~", "\"(\"|x$y"]),
+        ];
+        for (text, expected) in v {
+            println!("\ninput: {:?}", text);
+            let words = expected.iter().map(|x| x.to_string()).collect();
+            let stmts = parse_script(text).unwrap();
+            assert_eq!(stmts[0].node, Node::Cond(words));
+        }
+
+        let stmts = parse_script("[[ -d foo ]] && cd foo").unwrap();
+        assert_eq!(
+            stmts[0].node,
+            Node::Cond(vec!["-d".to_string(), "foo".to_string()])
+        );
+        assert_eq!(stmts[1], cmd("&&", "cd foo"));
+        let stmts = parse_script("[[ -d foo ]] | cat").unwrap();
+        assert_eq!(stmts, vec![cmd(";", "( [[ -d foo ]] ) | cat")]);
+    }
+
+    #[test]
+    fn test_parse_heredocs() {
+        let v = vec![
+            ("cat <<EOF\nfoo $x\nEOF\n", "cat <<EOF\nfoo $x\nEOF\n"),
+            ("cat <<EOF\nfoo\nEOF", "cat <<EOF\nfoo\nEOF\n"),
+            (
+                "cat << 'EOF' | wc -l\nfoo\n  EOF\nEOF\n",
+                "cat <<'EOF'\nfoo\n  EOF\nEOF\n | wc -l",
+            ),
+            (
+                "cat <<-EOF\n\tfoo\n\t\tbar\n\tEOF\n",
+                "cat <<-EOF\nfoo\nbar\nEOF\n",
+            ),
+            ("cat<<EOF\nEOF\n", "cat <<EOF\nEOF\n"),
+            ("cat <<<$x <<< \"$y\"", "cat <<<$x <<< \"$y\""),
+        ];
+        for (text, expected) in v {
+            println!("\ninput: {:?}", text);
+            assert_eq!(parse_script(text), Ok(vec![cmd(";", expected)]));
+        }
+
+        let stmts = parse_script("cat <<A; cat <<B\na\nA\nb\nB\necho done").unwrap();
+        assert_eq!(
+            stmts,
+            vec![
+                cmd(";", "cat <<A\na\nA\n"),
+                cmd(";", "cat <<B\nb\nB\n"),
+                cmd(";", "echo done"),
+            ]
+        );
+
+        // commands kept this way can be parsed again, e.g. in subshells
+        let text = "cat <<EOF\nfoo\nEOF\n | wc -l";
+        assert_eq!(parse_script(text), Ok(vec![cmd(";", text)]));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let v = vec![
+            "fi",
+            "then echo foo",
+            "if true; then fi",
+            "if ; then echo a; fi",
+            "echo a; fi",
+            "if true; then echo; fi foo",
+            "ls | | wc",
+            "for i in a b; do done",
+            "for 1x in a; do echo; done",
+            "for ((i = 0; i < 3)); do echo; done",
+            "while; do echo; done",
+            "echo a; done",
+            "echo a;; echo b",
+            "case a in ) echo;; esac",
+            "case a in a b) echo;; esac",
+            "case a; esac",
+            "case a in a) for i in b; do echo;; done;; esac",
+            "foo() { }",
+            "foo() { echo; } }",
+            "function 'a b' { echo; }",
+            "}",
+            "( )",
+            "echo a (b)",
+            "{ }",
+            ")",
+            "cat <<",
+            "cat << | wc",
+            "[[ ]]",
+            "[[ -f foo; ]]",
+            "[[ a | b ]]",
+        ];
+        for text in v {
+            println!("\ninput: {:?}", text);
+            match parse_script(text) {
+                Err(ParseError::Syntax(_)) => {}
+                x => panic!("unexpected result: {:?}", x),
+            }
+        }
+
+        assert!(is_complete("if true; then echo foo; fi"));
+        assert!(!is_complete("if true; then"));
+        assert!(!is_complete("if true; then\necho foo\n"));
+        assert!(!is_complete("if true; then echo foo; else"));
+        assert!(!is_complete("echo 'foo"));
+        assert!(!is_complete("echo $(date"));
+        assert!(!is_complete("[[ -f foo &&"));
+        assert!(!is_complete("echo foo &&"));
+        assert!(!is_complete("echo foo |"));
+        assert!(!is_complete("for i in a b; do"));
+        assert!(!is_complete("while true\ndo\n  echo\n"));
+        assert!(!is_complete("for ((i = 0;"));
+        assert!(!is_complete("case a in"));
+        assert!(!is_complete("case a in\n  a) echo a;;\n"));
+        assert!(!is_complete("foo() {"));
+        assert!(!is_complete("function foo {\n  echo foo\n"));
+        assert!(!is_complete("(cd foo\n"));
+        assert!(!is_complete("{ echo a;"));
+        assert!(!is_complete("cat <<EOF"));
+        assert!(!is_complete("cat <<EOF\nfoo\n  EOF\n"));
+    }
+}
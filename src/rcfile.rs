@@ -1,14 +1,12 @@
-use regex::Regex;
 use std::env;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::fs;
+use std::io::Write;
 use std::path::Path;
 
-use crate::builtins;
 use crate::parsers;
+use crate::scripting;
 use crate::shell;
 use crate::tools;
-use crate::types;
 
 fn load_file(sh: &mut shell::Shell, file_path: &str, count: i32) {
     if count > 99 {
@@ -28,25 +26,30 @@ fn load_file(sh: &mut shell::Shell, file_path: &str, count: i32) {
         return;
     }
 
-    let mut file;
-    match File::open(&rc_file) {
-        Ok(x) => file = x,
+    let text = match fs::read_to_string(&rc_file) {
+        Ok(x) => x,
         Err(e) => {
-            println!("cicada: open rcfile err: {:?}", e);
+            println_stderr!("cicada: {}: {}", rc_file, e);
             return;
         }
-    }
-    let mut text = String::new();
-    match file.read_to_string(&mut text) {
-        Ok(_) => {}
-        Err(e) => {
-            println!("cicada: read_to_string error: {:?}", e);
-            return;
-        }
-    }
+    };
+
+    // `include foo` loads another rc file in place, other lines run with
+    // the full syntax, like the ones of files run with `source`.
+    let mut buffer = String::new();
     for line in text.lines() {
-        handle_line(sh, line, count);
+        let tokens = parsers::parser_line::cmd_to_tokens(line);
+        let is_include = tokens.len() == 2 && tokens[0].1 == "include";
+        if is_include && parsers::parser_script::is_complete(&buffer) {
+            scripting::run_lines(sh, &buffer);
+            buffer.clear();
+            load_file(sh, &tokens[1].1, count + 1);
+            continue;
+        }
+        buffer.push_str(line);
+        buffer.push('\n');
     }
+    scripting::run_lines(sh, &buffer);
 }
 
 pub fn load_rc_files(sh: &mut shell::Shell) {
@@ -61,41 +64,3 @@ pub fn load_rc_files(sh: &mut shell::Shell) {
     let rc_file = tools::get_rc_file();
     load_file(sh, &rc_file, 1);
 }
-
-fn handle_line(sh: &mut shell::Shell, line: &str, count: i32) {
-    let mut tokens = parsers::parser_line::cmd_to_tokens(line);
-    if tokens.len() == 2 && tokens[0].1 == "include" {
-        load_file(sh, &tokens[1].1, count + 1);
-        return;
-    }
-
-    shell::do_expansion(sh, &mut tokens);
-    if tools::is_export_env(line) {
-        handle_env(sh, &tokens);
-        return;
-    }
-    if tools::is_alias(line) {
-        handle_alias(sh, line);
-        return;
-    }
-}
-
-fn handle_env(sh: &shell::Shell, tokens: &types::Tokens) {
-    builtins::export::run(sh, tokens);
-}
-
-fn handle_alias(sh: &mut shell::Shell, line: &str) {
-    let re;
-    match Regex::new(r"^ *alias +([a-zA-Z0-9_\.-]+)=(.*)$") {
-        Ok(x) => re = x,
-        Err(e) => {
-            println!("cicada: Regex error: {:?}", e);
-            return;
-        }
-    }
-    for cap in re.captures_iter(line) {
-        let name = tools::unquote(&cap[1]);
-        let value = tools::unquote(&cap[2]);
-        sh.add_alias(name.as_str(), value.as_str());
-    }
-}
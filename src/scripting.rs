@@ -0,0 +1,697 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+
+use crate::builtins;
+use crate::execute;
+use crate::jobc;
+use crate::parsers::parser_line;
+use crate::parsers::parser_script::{self, ParseError};
+use crate::shell;
+use crate::tools::clog;
+use crate::types::{self, Node, Stmt};
+
+/// Run a script file, e.g. `cicada foo.sh arg1 arg2`.
+/// `args[0]` is the path of the script, the rest are its arguments.
+pub fn run_script(sh: &mut shell::Shell, args: &[String]) -> i32 {
+    let src_file = &args[0];
+    let full_src_file: String = if src_file.contains('/') {
+        src_file.clone()
+    } else {
+        let full_path = Path::new(".").join(src_file);
+        full_path.to_string_lossy().to_string()
+    };
+    if !Path::new(&full_src_file).exists() {
+        println_stderr!("cicada: {}: No such file or directory", src_file);
+        return 127;
+    }
+
+    // read it all, so that no fd is held open while the script runs
+    let text = match fs::read_to_string(&full_src_file) {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: {}: {}", src_file, e);
+            return 126;
+        }
+    };
+
+    log!("run script: {}", &full_src_file);
+    sh.args = args.to_vec();
+    run_lines(sh, &text)
+}
+
+/// Run lines of a script one by one, returns status of the last command.
+/// Commands over multiple lines (e.g. `if` ... `fi`) run when complete.
+pub fn run_lines(sh: &mut shell::Shell, text: &str) -> i32 {
+    let mut status = 0;
+    let mut buffer = String::new();
+    for line in text.lines() {
+        buffer.push_str(line);
+        buffer.push('\n');
+        match parser_script::parse_script(&buffer) {
+            Ok(stmts) => {
+                if !stmts.is_empty() {
+                    status = run_stmts(sh, &stmts, false);
+                }
+            }
+            Err(ParseError::Incomplete(_)) => {
+                continue;
+            }
+            Err(e) => {
+                println_stderr!("cicada: {}", e);
+                return 2;
+            }
+        }
+        buffer.clear();
+    }
+    if !buffer.is_empty() {
+        if let Err(e) = parser_script::parse_script(&buffer) {
+            println_stderr!("cicada: {}", e);
+        }
+        return 2;
+    }
+    status
+}
+
+/// Run each script of the cases with `run_lines()`, and check the value
+/// of `$S` after it, and that no loop is left running. Tests of the
+/// builtins and other modules use it too.
+#[cfg(test)]
+pub fn check<T: AsRef<str>>(sh: &mut shell::Shell, cases: &[(T, &str)]) {
+    for (text, expected) in cases {
+        let text = text.as_ref();
+        println!("\ninput: {:?}", text);
+        sh.set_env("S", "");
+        run_lines(sh, text);
+        assert_eq!(sh.get_env("S"), Some(expected.to_string()));
+        assert_eq!(sh.loop_depth, 0);
+        assert_eq!(sh.break_num, 0);
+        assert_eq!(sh.continue_num, 0);
+    }
+}
+
+/// Run parsed commands, returns status of the last one that ran.
+pub fn run_stmts(sh: &mut shell::Shell, stmts: &[Stmt], tty: bool) -> i32 {
+    let mut status = 0;
+    for (i, stmt) in stmts.iter().enumerate() {
+        if stmt.sep == "&&" && status != 0 {
+            continue;
+        }
+        if stmt.sep == "||" && status == 0 {
+            continue;
+        }
+
+        // `set -e` does not exit the shell for failed commands in
+        // conditions, i.e. the negated ones and the ones followed by
+        // `&&` or `||`, including the commands in them
+        let is_cond = stmt.negate
+            || stmts
+                .get(i + 1)
+                .is_some_and(|x| x.sep == "&&" || x.sep == "||");
+        if is_cond {
+            sh.cond_depth += 1;
+        }
+        status = run_node(sh, &stmt.node, tty);
+        if is_cond {
+            sh.cond_depth -= 1;
+        }
+        if stmt.negate {
+            status = if status == 0 { 1 } else { 0 };
+        }
+        sh.previous_status = status;
+        builtins::trap::run_pending(sh);
+
+        let failed = status != 0 && status != types::INTERRUPTED;
+        if failed && sh.cond_depth == 0 && !is_cond {
+            // like bash, the `ERR` trap is not for commands in functions
+            if sh.scopes.is_empty() {
+                builtins::trap::run_err(sh);
+            }
+            if sh.options.errexit {
+                builtins::trap::run_exit(sh);
+                process::exit(status);
+            }
+        }
+
+        // stop the list for `break`, `continue`, `return` and Ctrl-C
+        if sh.break_num > 0 || sh.continue_num > 0 || sh.returning || status == types::INTERRUPTED {
+            break;
+        }
+    }
+    status
+}
+
+fn run_node(sh: &mut shell::Shell, node: &Node, tty: bool) -> i32 {
+    // process substitutions like `<(ls)` are done with the command
+    let proc_subs_num = sh.proc_subs.len();
+    let status = match node {
+        Node::Cmd(line) => execute::run_proc(sh, line, tty),
+        Node::If(branches, else_body) => run_if(sh, branches, else_body, tty),
+        Node::For(name, words, body) => run_for(sh, name, words, body, tty),
+        Node::ForArith(init, cond, step, body) => run_for_arith(sh, init, cond, step, body, tty),
+        Node::Arith(expr) => run_arith(sh, expr),
+        Node::Cond(words) => builtins::test::run_cond(sh, words),
+        Node::While(cond, body) => run_while(sh, cond, body, false, tty),
+        Node::Until(cond, body) => run_while(sh, cond, body, true, tty),
+        Node::Case(word, items) => run_case(sh, word, items, tty),
+        Node::FuncDef(name, body) => {
+            sh.funcs.insert(name.clone(), body.clone());
+            0
+        }
+        Node::Group(body) => run_stmts(sh, body, tty),
+        Node::Redirect(node, redirects) => run_redirect(sh, node, redirects, tty),
+    };
+    jobc::wait_proc_subs(sh, proc_subs_num);
+    status
+}
+
+/// Run compound commands with redirections like `{ foo; bar; } > foo.log`
+/// in current shell process.
+fn run_redirect(sh: &mut shell::Shell, node: &Node, redirects: &str, tty: bool) -> i32 {
+    let mut tokens = parser_line::cmd_to_tokens(redirects);
+    shell::do_word_expansion(sh, &mut tokens);
+    let cmd = match parser_line::cmd_to_with_redirects(&tokens) {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: {}", e);
+            return 1;
+        }
+    };
+
+    let saved = match execute::apply_redirects(&cmd.redirects, sh.options.noclobber) {
+        Ok(x) => x,
+        Err(e) => {
+            println_stderr!("cicada: {}", e);
+            return 1;
+        }
+    };
+    let status = run_node(sh, node, tty);
+    execute::restore_fds(saved);
+    status
+}
+
+/// Run a function defined with `foo() { ... }`, `tokens` are the name of
+/// the function and its arguments, which become `$1`, `$2`, ... in it.
+pub fn run_func(sh: &mut shell::Shell, tokens: &types::Tokens, tty: bool) -> i32 {
+    let body = match sh.funcs.get(&tokens[0].1) {
+        Some(x) => x.clone(),
+        None => return 127,
+    };
+
+    let mut args: Vec<String> = tokens.iter().map(|x| x.1.clone()).collect();
+    // `$0` is not changed in functions
+    args[0] = sh.args.first().cloned().unwrap_or_default();
+    let args_saved = std::mem::replace(&mut sh.args, args);
+    // `break` and `continue` cannot go out of functions
+    let loop_depth_saved = sh.loop_depth;
+    sh.loop_depth = 0;
+    sh.scopes.push(types::Scope::default());
+
+    let status = run_stmts(sh, &body, tty);
+
+    sh.scopes.pop();
+    sh.loop_depth = loop_depth_saved;
+    sh.args = args_saved;
+    sh.returning = false;
+    status
+}
+
+fn run_if(
+    sh: &mut shell::Shell,
+    branches: &[(Vec<Stmt>, Vec<Stmt>)],
+    else_body: &[Stmt],
+    tty: bool,
+) -> i32 {
+    for (cond, body) in branches {
+        if run_cond(sh, cond, tty) == 0 {
+            return run_stmts(sh, body, tty);
+        }
+    }
+    // status of `if` is 0 when no condition is true and there is no else
+    run_stmts(sh, else_body, tty)
+}
+
+/// Run the condition of `if` or `while`, which `set -e` ignores.
+fn run_cond(sh: &mut shell::Shell, cond: &[Stmt], tty: bool) -> i32 {
+    sh.cond_depth += 1;
+    let status = run_stmts(sh, cond, tty);
+    sh.cond_depth -= 1;
+    status
+}
+
+/// Called after each round of a loop, tells whether the loop should stop.
+/// It consumes one level of pending `break N` / `continue N`.
+fn should_break(sh: &mut shell::Shell) -> bool {
+    if sh.returning {
+        return true;
+    }
+    if sh.break_num > 0 {
+        sh.break_num -= 1;
+        return true;
+    }
+    if sh.continue_num > 0 {
+        sh.continue_num -= 1;
+        // `continue N` continues an outer loop
+        return sh.continue_num > 0;
+    }
+    false
+}
+
+fn run_for(
+    sh: &mut shell::Shell,
+    name: &str,
+    words: &Option<String>,
+    body: &[Stmt],
+    tty: bool,
+) -> i32 {
+    let values = match words {
+        Some(x) => shell::expand_word_list(sh, x),
+        None => sh.args.iter().skip(1).cloned().collect(),
+    };
+
+    let mut status = 0;
+    sh.loop_depth += 1;
+    for value in values {
+        sh.set_env(name, &value);
+        status = run_stmts(sh, body, tty);
+        if should_break(sh) || status == types::INTERRUPTED {
+            break;
+        }
+    }
+    sh.loop_depth -= 1;
+    status
+}
+
+fn eval_arith(sh: &mut shell::Shell, expr: &str) -> Result<i64, String> {
+    // empty expressions in `for ((;;))` are treated as true
+    if expr.trim().is_empty() {
+        return Ok(1);
+    }
+    shell::eval_arith(sh, expr)
+}
+
+/// Run `(( expr ))`, which is true (i.e. status `0`) when the value of
+/// `expr` is not zero.
+fn run_arith(sh: &mut shell::Shell, expr: &str) -> i32 {
+    match shell::eval_arith(sh, expr) {
+        Ok(0) => 1,
+        Ok(_) => 0,
+        Err(e) => {
+            println_stderr!("cicada: {}", e);
+            1
+        }
+    }
+}
+
+fn run_for_arith(
+    sh: &mut shell::Shell,
+    init: &str,
+    cond: &str,
+    step: &str,
+    body: &[Stmt],
+    tty: bool,
+) -> i32 {
+    if let Err(e) = eval_arith(sh, init) {
+        println_stderr!("cicada: {}", e);
+        return 1;
+    }
+
+    let mut status = 0;
+    sh.loop_depth += 1;
+    loop {
+        match eval_arith(sh, cond) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                println_stderr!("cicada: {}", e);
+                status = 1;
+                break;
+            }
+        }
+        status = run_stmts(sh, body, tty);
+        if should_break(sh) || status == types::INTERRUPTED {
+            break;
+        }
+        if let Err(e) = eval_arith(sh, step) {
+            println_stderr!("cicada: {}", e);
+            status = 1;
+            break;
+        }
+    }
+    sh.loop_depth -= 1;
+    status
+}
+
+/// Run `while` loops, or `until` loops when `until` is true.
+fn run_while(sh: &mut shell::Shell, cond: &[Stmt], body: &[Stmt], until: bool, tty: bool) -> i32 {
+    let mut status = 0;
+    sh.loop_depth += 1;
+    loop {
+        let cond_status = run_cond(sh, cond, tty);
+        if cond_status == types::INTERRUPTED {
+            status = cond_status;
+            break;
+        }
+        if should_break(sh) || (cond_status == 0) == until {
+            break;
+        }
+        status = run_stmts(sh, body, tty);
+        if should_break(sh) || status == types::INTERRUPTED {
+            break;
+        }
+    }
+    sh.loop_depth -= 1;
+    status
+}
+
+/// Expand the word or a pattern of `case`. Quoted parts of patterns
+/// are escaped, so that they match literally.
+fn expand_case_word(sh: &mut shell::Shell, text: &str, is_pattern: bool) -> String {
+    let mut tokens = parser_line::cmd_to_tokens(text);
+    shell::expand_word(sh, &mut tokens);
+    let mut result = String::new();
+    for (sep, token) in tokens {
+        if is_pattern && !sep.is_empty() {
+            result.push_str(&glob::Pattern::escape(&token));
+        } else {
+            result.push_str(&token);
+        }
+    }
+    result
+}
+
+fn run_case(
+    sh: &mut shell::Shell,
+    word: &str,
+    items: &[(Vec<String>, Vec<Stmt>, String)],
+    tty: bool,
+) -> i32 {
+    let word = expand_case_word(sh, word, false);
+    let mut status = 0;
+    // set by `;&`, run the next body without testing its patterns
+    let mut fallthrough = false;
+    for (patterns, body, end) in items {
+        let matched = fallthrough
+            || patterns.iter().any(|x| {
+                let pattern = expand_case_word(sh, x, true);
+                shell::glob_match(&pattern, &word)
+            });
+        if !matched {
+            continue;
+        }
+
+        status = run_stmts(sh, body, tty);
+        if sh.break_num > 0 || sh.continue_num > 0 || sh.returning || status == types::INTERRUPTED {
+            break;
+        }
+        // `;;&` goes on testing the patterns of next items
+        fallthrough = end == ";&";
+        if end == ";;" {
+            break;
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::{check, run_lines};
+    use crate::execute;
+    use crate::shell;
+
+    #[test]
+    fn test_run_lines() {
+        let mut sh = shell::Shell::new();
+        sh.args = vec!["foo.sh".to_string(), "arg1".to_string()];
+        let text = "#!/usr/bin/env cicada\n\n# comment\nFOO=bar\nBAZ=$FOO-$1\n";
+        assert_eq!(run_lines(&mut sh, text), 0);
+        assert_eq!(sh.get_env("FOO"), Some("bar".to_string()));
+        assert_eq!(sh.get_env("BAZ"), Some("bar-arg1".to_string()));
+    }
+
+    #[test]
+    fn test_run_if() {
+        let mut sh = shell::Shell::new();
+        execute::run_procs(&mut sh, "if true; then FOO=1; else FOO=2; fi", false);
+        assert_eq!(sh.get_env("FOO"), Some("1".to_string()));
+        execute::run_procs(&mut sh, "if false; then FOO=1; else FOO=2; fi", false);
+        assert_eq!(sh.get_env("FOO"), Some("2".to_string()));
+        execute::run_procs(
+            &mut sh,
+            "if false; then FOO=1; elif ! false; then FOO=3; fi",
+            false,
+        );
+        assert_eq!(sh.get_env("FOO"), Some("3".to_string()));
+
+        let text = "if [ -d /nonexist-dir ]\nthen\n  BAR=1\nelif [ -d / ]; then\n  BAR=2\nfi\n";
+        assert_eq!(run_lines(&mut sh, text), 0);
+        assert_eq!(sh.get_env("BAR"), Some("2".to_string()));
+
+        assert_eq!(
+            execute::run_procs(&mut sh, "if false; then true; fi", false),
+            0
+        );
+        assert_eq!(
+            execute::run_procs(&mut sh, "if true; then false; fi", false),
+            1
+        );
+        assert_eq!(run_lines(&mut sh, "if true; then\necho foo\n"), 2);
+    }
+
+    #[test]
+    fn test_run_loops() {
+        let mut sh = shell::Shell::new();
+        sh.args = vec!["foo.sh".to_string(), "x".to_string(), "y".to_string()];
+        let v = [
+            ("for i in a b c; do S=$S$i; done", "abc"),
+            ("for i in a 'b c' d; do S=$S-$i; done", "-a-b c-d"),
+            ("L='a b'; for i in $L x; do S=$S$i; done", "abx"),
+            ("for i; do S=$S$i; done", "xy"),
+            ("for i in a b\ndo\n  S=$S$i\ndone\n", "ab"),
+            ("for ((i = 0; i < 3; i++)); do S=$S$i; done", "012"),
+            ("for ((i = 5; i > 0; i -= 2))\ndo\n  S=$S$i\ndone\n", "531"),
+            ("i=a; while [ $i != aaa ]; do S=$S$i; i=${i}a; done", "aaa"),
+            (
+                "i=a; until [ $i = aaa ]; do S=$S-$i; i=${i}a; done",
+                "-a-aa",
+            ),
+            (
+                "for i in 1 2 3 4; do if [ $i = 3 ]; then break; fi; S=$S$i; done",
+                "12",
+            ),
+            (
+                "for i in 1 2 3; do if [ $i = 2 ]; then continue; fi; S=$S$i; done",
+                "13",
+            ),
+            ("for ((;;)); do S=x; break; S=y; done", "x"),
+            (
+                "for i in 1 2; do for j in a b; do S=$S$i$j; break 2; done; done",
+                "1a",
+            ),
+            (
+                "for i in 1 2; do for j in a b; do S=$S$i$j; continue 2; S=no; done; S=$S-; done",
+                "1a2a",
+            ),
+            (
+                "for i in 1 2; do for j in a b c; do [ $j = b ] && continue; S=$S$i$j; done; done",
+                "1a1c2a2c",
+            ),
+        ];
+        check(&mut sh, &v);
+
+        assert_eq!(execute::run_procs(&mut sh, "break", false), 1);
+        assert_eq!(
+            execute::run_procs(&mut sh, "while false; do true; done", false),
+            0
+        );
+        assert_eq!(
+            execute::run_procs(&mut sh, "for i in a; do false; done", false),
+            1
+        );
+        assert_eq!(
+            execute::run_procs(&mut sh, "for ((i = 0; i < 1 +; i++)); do true; done", false),
+            1
+        );
+    }
+
+    #[test]
+    fn test_run_case() {
+        let mut sh = shell::Shell::new();
+        let v = [
+            ("X=foo.rs; case $X in *.py) S=py;; *.rs) S=rs;; esac", "rs"),
+            ("case foo in bar|f?o) S=1;; foo) S=2;; esac", "1"),
+            ("case cicada in [a-c]*) S=abc;; esac", "abc"),
+            ("case foo in bar) S=bar;; esac", ""),
+            (
+                "case $(echo foo) in foo) S=$S-foo;; *) S=$S-any;; esac",
+                "-foo",
+            ),
+            (
+                "case foo in foo) S=$S-a;& bar) S=$S-b;& baz) S=$S-c;; *) S=$S-d;; esac",
+                "-a-b-c",
+            ),
+            (
+                "case foo in f*) S=$S-a;;& bar) S=$S-b;; *o) S=$S-c;; *) S=$S-d;; esac",
+                "-a-c",
+            ),
+            (
+                "case 'a*' in 'a*') S=$S-q;; esac; case ab in 'a*') S=$S-x;; esac",
+                "-q",
+            ),
+            ("Y=foo; case foo in \"$Y\") S=y;; esac", "y"),
+            (
+                "for i in 1 2 3; do case $i in 2) break;; esac; S=$S$i; done",
+                "1",
+            ),
+        ];
+        check(&mut sh, &v);
+
+        assert_eq!(
+            execute::run_procs(&mut sh, "case a in b) true;; esac", false),
+            0
+        );
+        assert_eq!(
+            execute::run_procs(&mut sh, "case a in a) false;; esac", false),
+            1
+        );
+    }
+
+    #[test]
+    fn test_run_func() {
+        let mut sh = shell::Shell::new();
+        sh.args = vec!["foo.sh".to_string(), "x".to_string()];
+        let text = "
+foo() {
+    S=\"$S [$0 $# $1 $2 $@]\"
+}
+function bar {
+    for i; do
+        [ $i = 2 ] && return 3
+        S=$S$i
+    done
+    S=${S}end
+}
+";
+        assert_eq!(run_lines(&mut sh, text), 0);
+        assert!(sh.funcs.contains_key("foo"));
+        assert!(sh.funcs.contains_key("bar"));
+
+        let v = vec![
+            ("foo a 'b c'", "[foo.sh 2 a b c a b c]", 0),
+            ("foo; foo z", "[foo.sh 0   ] [foo.sh 1 z  z]", 0),
+            ("bar 1 3", "13end", 0),
+            ("bar 1 2 3", "1", 3),
+            ("for i in 1 2; do bar $i; S=$S-; done", "1end--", 0),
+            ("bar 2 || S=failed", "failed", 0),
+            ("foo a > /dev/null 2>&1", "[foo.sh 1 a  a]", 0),
+            ("bar 1 2 < /dev/null", "1", 3),
+        ];
+        for (text, expected, status) in v {
+            println!("\ninput: {:?}", text);
+            sh.set_env("S", "");
+            assert_eq!(execute::run_procs(&mut sh, text, false), status);
+            assert_eq!(sh.get_env("S").unwrap().trim(), expected);
+        }
+        assert_eq!(sh.args, vec!["foo.sh".to_string(), "x".to_string()]);
+        assert!(sh.scopes.is_empty());
+        assert!(!sh.returning);
+
+        assert_eq!(execute::run_procs(&mut sh, "return 2", false), 1);
+        assert_eq!(
+            execute::run_procs(&mut sh, "baz() { return 258; }; baz", false),
+            2
+        );
+    }
+
+    #[test]
+    fn test_run_groups() {
+        let mut sh = shell::Shell::new();
+        let text = "
+x=1
+{ x=2; y=2; }
+(x=3; y=3; exit 5)
+S=\"$? $x $y\"
+";
+        assert_eq!(run_lines(&mut sh, text), 0);
+        assert_eq!(sh.get_env("S"), Some("5 2 2".to_string()));
+
+        let file = env::temp_dir().join(format!("cicada-groups-{}.log", process::id()));
+        let file = file.to_str().unwrap();
+        let text = format!(
+            "{{ echo a; echo b 1>&2; }} > {} 2>&1; {{ echo c; }} >> {}",
+            file, file
+        );
+        assert_eq!(execute::run_procs(&mut sh, &text, false), 0);
+        assert_eq!(fs::read_to_string(file).unwrap(), "a\nb\nc\n");
+        let _ = fs::remove_file(file);
+    }
+
+    #[test]
+    fn test_run_heredocs() {
+        let mut sh = shell::Shell::new();
+        let file = env::temp_dir().join(format!("cicada-heredocs-{}.log", process::id()));
+        let file = file.to_str().unwrap();
+        let text = format!(
+            "
+x=foo
+cat <<EOF > {0}
+x is $x, $(echo bar)
+EOF
+cat <<'EOF' >> {0}
+x is $x
+EOF
+\tcat <<-EOF | wc -l >> {0}
+\t\tone
+\ttwo
+\tEOF
+{{ cat; echo end; }} >> {0} <<EOF
+grouped
+EOF
+cat <<EOF >> {0}
+\\$x \\\\ \\`x\\` \\y \\
+$x
+EOF
+",
+            file
+        );
+        assert_eq!(run_lines(&mut sh, &text), 0);
+        let output = fs::read_to_string(file).unwrap();
+        let lines: Vec<&str> = output.lines().map(|x| x.trim()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "x is foo, bar",
+                "x is $x",
+                "2",
+                "grouped",
+                "end",
+                "$x \\ `x` \\y foo"
+            ]
+        );
+        let _ = fs::remove_file(file);
+    }
+
+    #[test]
+    fn test_run_command_substitution() {
+        let mut sh = shell::Shell::new();
+        let v = [
+            ("S=$(dirname $(dirname /a/b/c))", "/a"),
+            ("S=\"$(echo \")\" 'x  y')\"", ") x  y"),
+            ("S=$(for i in 1 2; do\n  echo $i\ndone\n)", "1\n2"),
+            ("S=\"[$(printf ' a \\n\\n')]\"", "[ a ]"),
+            ("for i in $(echo a b) `echo c`; do S=$S$i; done", "abc"),
+            ("f() { echo \"f $1\"; }; S=$(f x)", "f x"),
+            ("S=x$()y", "xy"),
+            ("S=$(case a in a) echo b;; esac)", "b"),
+            ("S=\"$(case a in (a) echo b;; esac) $(echo ')')\"", "b )"),
+            ("x=$(exit 3); S=$?; x=$(true); S=$S$?", "30"),
+            ("if x=$(false); then S=yes; else S=no; fi", "no"),
+        ];
+        check(&mut sh, &v);
+    }
+}
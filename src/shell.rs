@@ -1,13 +1,16 @@
 use errno::errno;
 use libc;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::io::Write;
 use std::mem;
+use std::process;
 
 use glob;
 use regex::Regex;
 
+use crate::arith;
+use crate::builtins;
 use crate::execute;
 use crate::libs;
 use crate::parsers;
@@ -23,6 +26,43 @@ pub struct Shell {
     pub previous_dir: String,
     pub previous_cmd: String,
     pub previous_status: i32,
+    /// Status of the last command substitution like `$(date)` in current
+    /// command, which is the status of assignments like `x=$(date)`.
+    pub subst_status: Option<i32>,
+    /// Positional parameters, `args[0]` is `$0`.
+    pub args: Vec<String>,
+    /// How many loops we are in, and pending `break N` / `continue N`.
+    pub loop_depth: i32,
+    pub break_num: i32,
+    pub continue_num: i32,
+    /// functions defined with `foo() { ... }`
+    pub funcs: HashMap<String, Vec<types::Stmt>>,
+    /// Local variables of the function calls we are in, the innermost
+    /// call is the last one.
+    pub scopes: Vec<types::Scope>,
+    /// Whether `return` is pending.
+    pub returning: bool,
+    /// The pipe fds kept in the shell and the pids of process
+    /// substitutions like `<(ls)`, see `jobc::wait_proc_subs()`.
+    pub proc_subs: Vec<(i32, i32)>,
+    /// Set when expansions like `${foo:?not set}` fail, then the command
+    /// would not run.
+    pub expand_failed: bool,
+    /// Array variables like `arr=(a b c)` and `declare -A map`.
+    pub arrays: HashMap<String, types::Array>,
+    /// The result of the previous calculator line, i.e. `ans`.
+    pub calc_ans: String,
+    /// Options like `set -e`, see `builtins::set`.
+    pub options: types::ShellOptions,
+    /// How many conditions (e.g. of `if` and `while`) we are in, where
+    /// failed commands do not make `set -e` exit the shell.
+    pub cond_depth: i32,
+    /// Commands set with `trap`, keyed by `EXIT`, `ERR` or signal names
+    /// like `INT`.
+    pub traps: HashMap<String, String>,
+    /// Set when running scripts and `-c` commands, which exit on errors
+    /// like unbound variables with `set -u`.
+    pub non_interactive: bool,
 }
 
 impl Shell {
@@ -35,6 +75,22 @@ impl Shell {
             previous_dir: String::new(),
             previous_cmd: String::new(),
             previous_status: 0,
+            subst_status: None,
+            args: Vec::new(),
+            loop_depth: 0,
+            break_num: 0,
+            continue_num: 0,
+            funcs: HashMap::new(),
+            scopes: Vec::new(),
+            returning: false,
+            proc_subs: Vec::new(),
+            expand_failed: false,
+            arrays: HashMap::new(),
+            calc_ans: String::from("0"),
+            options: types::ShellOptions::default(),
+            cond_depth: 0,
+            traps: HashMap::new(),
+            non_interactive: false,
         }
     }
 
@@ -175,6 +231,21 @@ impl Shell {
     }
 
     pub fn set_env(&mut self, name: &str, value: &str) {
+        if let Some(scope) = self.scopes.iter_mut().rev().find(|x| x.contains(name)) {
+            match scope.arrays.get_mut(name) {
+                Some(x) => x.set("0", value),
+                None => {
+                    scope.envs.insert(name.to_string(), value.to_string());
+                }
+            }
+            return;
+        }
+        // `arr=foo` sets the first item of array arr
+        if let Some(x) = self.arrays.get_mut(name) {
+            x.set("0", value);
+            return;
+        }
+
         if env::var(name).is_ok() {
             env::set_var(name, value);
         } else {
@@ -183,9 +254,75 @@ impl Shell {
     }
 
     pub fn get_env(&self, name: &str) -> Option<String> {
-        match self.envs.get(name) {
-            Some(x) => Some(x.to_string()),
-            None => None,
+        if let Some(x) = self.get_local_env(name) {
+            return Some(x);
+        }
+        self.envs.get(name).map(|x| x.to_string())
+    }
+
+    /// Get variables declared with `local` in the function calls, the
+    /// first item for local arrays.
+    pub fn get_local_env(&self, name: &str) -> Option<String> {
+        let scope = self.scopes.iter().rev().find(|x| x.contains(name))?;
+        match scope.arrays.get(name) {
+            Some(x) => x.get("0").cloned(),
+            None => scope.envs.get(name).cloned(),
+        }
+    }
+
+    /// Declare a local variable in current function call, returns false
+    /// if we are not in a function.
+    pub fn set_local_env(&mut self, name: &str, value: &str) -> bool {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.arrays.remove(name);
+                scope.envs.insert(name.to_string(), value.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Declare a local array in current function call, returns false if
+    /// we are not in a function.
+    pub fn set_local_array(&mut self, name: &str, array: types::Array) -> bool {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.envs.remove(name);
+                scope.arrays.insert(name.to_string(), array);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The array `name`, local ones of the function calls go first. None
+    /// if it is not an array, e.g. a local variable shadowing an array.
+    pub fn get_array(&self, name: &str) -> Option<&types::Array> {
+        match self.scopes.iter().rev().find(|x| x.contains(name)) {
+            Some(scope) => scope.arrays.get(name),
+            None => self.arrays.get(name),
+        }
+    }
+
+    pub fn get_array_mut(&mut self, name: &str) -> Option<&mut types::Array> {
+        match self.scopes.iter_mut().rev().find(|x| x.contains(name)) {
+            Some(scope) => scope.arrays.get_mut(name),
+            None => self.arrays.get_mut(name),
+        }
+    }
+
+    /// Set array `name`, which replaces the (local) variable of the name.
+    pub fn set_array(&mut self, name: &str, array: types::Array) {
+        match self.scopes.iter_mut().rev().find(|x| x.contains(name)) {
+            Some(scope) => {
+                scope.envs.remove(name);
+                scope.arrays.insert(name.to_string(), array);
+            }
+            None => {
+                self.envs.remove(name);
+                self.arrays.insert(name.to_string(), array);
+            }
         }
     }
 
@@ -270,6 +407,15 @@ fn needs_globbing(line: &str) -> bool {
     false
 }
 
+/// Does `text` match the glob `pattern`, e.g. `*.rs`, `foo?` or `[a-c]*`?
+/// The syntax is the same as in `expand_glob()`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    match glob::Pattern::new(pattern) {
+        Ok(x) => x.matches(text),
+        Err(_) => pattern == text,
+    }
+}
+
 pub fn expand_glob(tokens: &mut types::Tokens) {
     let mut idx: usize = 0;
 
@@ -342,58 +488,483 @@ pub fn expand_glob(tokens: &mut types::Tokens) {
     }
 }
 
-pub fn extend_env_blindly(sh: &Shell, token: &str) -> String {
-    let re;
-    if let Ok(x) = Regex::new(r"([^\$]*)\$\{?([A-Za-z0-9\?\$_]+)\}?(.*)") {
-        re = x;
+/// Expand parameters like `$foo`, `${foo}`, `$1` and `${foo:-bar}` in
+/// `token`, see `expand_param()` for the forms in braces.
+pub fn extend_env_blindly(sh: &mut Shell, token: &str) -> String {
+    let chars: Vec<char> = token.chars().collect();
+    let mut result = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '$' || i + 1 >= chars.len() {
+            result.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        if chars[i + 1] == '{' {
+            let end = find_closing_brace(&chars, i + 1);
+            if end >= chars.len() {
+                // `${foo` without the `}`
+                result.extend(chars[i..].iter());
+                break;
+            }
+            let expr: String = chars[i + 2..end].iter().collect();
+            result.push_str(&expand_param(sh, &expr));
+            i = end + 1;
+            continue;
+        }
+
+        let mut j = i + 1;
+        // `$10` is `${1}0`, only `${10}` means the 10th parameter
+        if "?$@*#".contains(chars[j]) || chars[j].is_ascii_digit() {
+            j += 1;
+        } else {
+            while j < chars.len() && (chars[j].is_ascii_alphanumeric() || chars[j] == '_') {
+                j += 1;
+            }
+        }
+        if j == i + 1 {
+            result.push('$');
+            i += 1;
+            continue;
+        }
+        let name: String = chars[i + 1..j].iter().collect();
+        result.push_str(&get_param_checked(sh, &name));
+        i = j;
+    }
+    result
+}
+
+/// Returns index of the `}` matching the `{` at `start`, or the length
+/// of `chars` if it is missing.
+fn find_closing_brace(chars: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        if chars[i] == '\\' {
+            i += 2;
+            continue;
+        }
+        if chars[i] == '{' {
+            depth += 1;
+        } else if chars[i] == '}' {
+            depth -= 1;
+            if depth == 0 {
+                return i;
+            }
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// Like `get_param()`, but with `set -u`, using an unset parameter is an
+/// error.
+fn get_param_checked(sh: &mut Shell, name: &str) -> String {
+    if let Some(x) = get_param(sh, name) {
+        return x;
+    }
+    if sh.options.nounset && !name.ends_with("[@]") && !name.ends_with("[*]") {
+        println_stderr!("cicada: {}: unbound variable", name);
+        fail_expansion(sh);
+    }
+    String::new()
+}
+
+/// Fail the command for errors like unbound variables with `set -u` and
+/// `${foo:?msg}`, and exit the shell if it is not interactive.
+fn fail_expansion(sh: &mut Shell) {
+    sh.expand_failed = true;
+    if sh.non_interactive {
+        builtins::trap::run_exit(sh);
+        process::exit(1);
+    }
+}
+
+/// The value of parameter `name` (e.g. `HOME`, `1` or `?`), or None if
+/// it is not set.
+fn get_param(sh: &mut Shell, name: &str) -> Option<String> {
+    // items of arrays, e.g. `arr[1]`, `map[$key]` and `arr[@]`
+    if let Some(i) = name.find('[') {
+        let base = &name[..i];
+        let sub = &name[i + 1..name.len() - 1];
+        if sub == "@" || sub == "*" {
+            return match sh.get_array(base) {
+                Some(x) => Some(x.values().join(" ")),
+                None => get_param(sh, base),
+            };
+        }
+        let key = match array_key(sh, base, sub) {
+            Ok(x) => x,
+            Err(e) => {
+                println_stderr!("cicada: {}", e);
+                sh.expand_failed = true;
+                return None;
+            }
+        };
+        return match sh.get_array(base) {
+            Some(x) => x.get(&key).cloned(),
+            // `${foo[0]}` is the same as `$foo`
+            None if key == "0" => get_param(sh, base),
+            None => None,
+        };
+    }
+
+    match name {
+        "?" => return Some(sh.previous_status.to_string()),
+        "$" => return Some(unsafe { libc::getpid() }.to_string()),
+        "@" | "*" => {
+            let args: Vec<String> = sh.args.iter().skip(1).cloned().collect();
+            return Some(args.join(" "));
+        }
+        "#" => return Some(sh.args.len().saturating_sub(1).to_string()),
+        _ => {}
+    }
+    if let Ok(i) = name.parse::<usize>() {
+        // positional parameters: $0, $1, ...
+        return sh.args.get(i).cloned();
+    }
+    if let Some(x) = sh.get_array(name) {
+        // `$arr` is the same as `${arr[0]}`
+        return x.get("0").cloned();
+    }
+    if let Some(x) = sh.get_local_env(name) {
+        return Some(x);
+    }
+    if let Ok(x) = env::var(name) {
+        return Some(x);
+    }
+    sh.get_env(name)
+}
+
+/// Split `expr` of `${expr}` into the parameter name and the rest, e.g.
+/// `foo:-bar` into `foo` and `:-bar`.
+fn split_param(expr: &str) -> (&str, &str) {
+    let first = match expr.chars().next() {
+        Some(x) => x,
+        None => return ("", ""),
+    };
+    let len = if "?$@*#".contains(first) {
+        1
+    } else if first.is_ascii_digit() {
+        expr.chars().take_while(|x| x.is_ascii_digit()).count()
+    } else if first.is_ascii_alphabetic() || first == '_' {
+        let len = expr
+            .chars()
+            .take_while(|x| x.is_ascii_alphanumeric() || *x == '_')
+            .count();
+        // with the subscript of arrays, e.g. `arr[1]`
+        match expr[len..].find(']') {
+            Some(i) if expr[len..].starts_with('[') => len + i + 1,
+            _ => len,
+        }
     } else {
-        println!("cicada: re new error");
-        return String::new();
+        0
+    };
+    expr.split_at(len)
+}
+
+/// The key of the array item `name[sub]`: arithmetic for indexed arrays,
+/// e.g. `arr[i + 1]` and `arr[-1]` (the last item), and text for
+/// associative arrays, e.g. `map[$name]`.
+fn array_key(sh: &mut Shell, name: &str, sub: &str) -> Result<String, String> {
+    let array = sh.get_array(name);
+    let is_assoc = matches!(array, Some(types::Array::Assoc(_)));
+    let next_index = array.map_or(0, |x| x.next_index());
+    item_key(sh, name, sub, is_assoc, next_index)
+}
+
+fn item_key(
+    sh: &mut Shell,
+    name: &str,
+    sub: &str,
+    is_assoc: bool,
+    next_index: usize,
+) -> Result<String, String> {
+    let sub = extend_env_blindly(sh, sub);
+    if is_assoc {
+        return Ok(sub);
     }
-    if !re.is_match(token) {
-        return token.to_string();
+    let mut i = match arith::eval(sh, &sub) {
+        Ok(x) => x,
+        Err(e) => return Err(format!("{}: {}", sub, e)),
+    };
+    if i < 0 {
+        i += next_index as i64;
+        if i < 0 {
+            return Err(format!("{}[{}]: bad array subscript", name, sub));
+        }
     }
+    Ok(i.to_string())
+}
 
-    let mut result = String::new();
-    let mut _token = token.to_string();
-    let mut _head = String::new();
-    let mut _output = String::new();
-    let mut _tail = String::new();
-    loop {
-        if !re.is_match(&_token) {
-            if !_token.is_empty() {
-                result.push_str(&_token);
+fn bad_substitution(sh: &mut Shell, expr: &str) -> String {
+    println_stderr!("cicada: ${{{}}}: bad substitution", expr);
+    sh.expand_failed = true;
+    String::new()
+}
+
+/// Expand `expr` of `${expr}`, which could be:
+/// - `foo`, `#foo` (length of the value)
+/// - `foo:-word`, `foo:=word`, `foo:?word`, `foo:+word`, and the forms
+///   without `:`, which do not treat empty values as unset
+/// - `foo#pat`, `foo##pat`, `foo%pat`, `foo%%pat` (remove prefix/suffix)
+/// - `foo/pat/word`, `foo//pat/word` (replace the first/all matches)
+/// - `foo:offset`, `foo:offset:length`
+/// - `foo^^`, `foo,,` (to upper/lower case), `foo^`, `foo,`
+///
+/// where `foo` could also be array items like `arr[1]` and `arr[@]`, and
+/// `#arr[@]` is the number of items, `!arr[@]` the keys (indexes) of them.
+fn expand_param(sh: &mut Shell, expr: &str) -> String {
+    if expr.len() > 1 && expr.starts_with('#') {
+        let (name, rest) = split_param(&expr[1..]);
+        if !name.is_empty() && rest.is_empty() {
+            if let Some(base) = array_all_items(name) {
+                let count = match sh.get_array(base) {
+                    Some(x) => x.values().len(),
+                    None => get_param(sh, base).map_or(0, |_| 1),
+                };
+                return count.to_string();
             }
-            break;
+            let value = get_param(sh, name).unwrap_or_default();
+            return value.chars().count().to_string();
         }
-        for cap in re.captures_iter(&_token) {
-            _head = cap[1].to_string();
-            _tail = cap[3].to_string();
-            let _key = cap[2].to_string();
-            if _key == "?" {
-                result.push_str(format!("{}{}", _head, sh.previous_status).as_str());
-            } else if _key == "$" {
-                unsafe {
-                    let val = libc::getpid();
-                    result.push_str(format!("{}{}", _head, val).as_str());
+    }
+    if let Some(base) = expr.strip_prefix('!').and_then(array_all_items) {
+        return match sh.get_array(base) {
+            Some(x) => x.keys().join(" "),
+            None => String::new(),
+        };
+    }
+
+    let (name, op) = split_param(expr);
+    if name.is_empty() {
+        return bad_substitution(sh, expr);
+    }
+    if op.is_empty() {
+        return get_param_checked(sh, name);
+    }
+    let value = get_param(sh, name);
+
+    let is_default_op = |x: &str| x.starts_with(|c| "-=?+".contains(c));
+    let colon = op.starts_with(':') && is_default_op(&op[1..]);
+    if colon || is_default_op(op) {
+        let op = if colon { &op[1..] } else { op };
+        let missing = match value {
+            Some(ref x) => colon && x.is_empty(),
+            None => true,
+        };
+        let value = value.unwrap_or_default();
+        return match &op[..1] {
+            "-" if missing => extend_env_blindly(sh, &op[1..]),
+            "=" if missing => {
+                if !tools::re_contains(name, r"^[a-zA-Z_]") {
+                    println_stderr!("cicada: ${}: cannot assign in this way", name);
+                    sh.expand_failed = true;
+                    return String::new();
                 }
-            } else if let Ok(val) = env::var(&_key) {
-                result.push_str(format!("{}{}", _head, val).as_str());
-            } else if let Some(val) = sh.get_env(&_key) {
-                result.push_str(format!("{}{}", _head, val).as_str());
-            } else {
-                result.push_str(&_head);
+                let word = extend_env_blindly(sh, &op[1..]);
+                sh.set_env(name, &word);
+                word
             }
+            "?" if missing => {
+                let mut msg = extend_env_blindly(sh, &op[1..]);
+                if msg.is_empty() {
+                    msg = String::from("parameter null or not set");
+                }
+                println_stderr!("cicada: {}: {}", name, msg);
+                fail_expansion(sh);
+                String::new()
+            }
+            "+" if missing => String::new(),
+            "+" => extend_env_blindly(sh, &op[1..]),
+            _ => value,
+        };
+    }
+
+    let value = value.unwrap_or_default();
+    for prefix in &["##", "#", "%%", "%"] {
+        if let Some(pattern) = op.strip_prefix(prefix) {
+            let pattern = extend_env_blindly(sh, pattern);
+            let longest = prefix.len() == 2;
+            return remove_pattern(&value, &pattern, prefix.starts_with('#'), longest);
+        }
+    }
+
+    if let Some(spec) = op.strip_prefix('/') {
+        let (all, spec) = match spec.strip_prefix('/') {
+            Some(x) => (true, x),
+            None => (false, spec),
+        };
+        // `/` in the pattern could be escaped like `${foo/\/usr/}`
+        let (pattern, word) = match find_unescaped_slash(spec) {
+            Some(i) => (&spec[..i], &spec[i + 1..]),
+            None => (spec, ""),
+        };
+        let pattern = extend_env_blindly(sh, &pattern.replace("\\/", "/"));
+        let word = extend_env_blindly(sh, word);
+        return replace_pattern(&value, &pattern, &word, all);
+    }
+
+    if let Some(spec) = op.strip_prefix(':') {
+        let (offset, length) = match spec.find(':') {
+            Some(i) => (&spec[..i], Some(&spec[i + 1..])),
+            None => (spec, None),
+        };
+        if offset.trim().is_empty() {
+            return bad_substitution(sh, expr);
+        }
+        // both are arithmetic expressions, e.g. `${foo:i+1:n-1}`
+        let offset = match eval_arith(sh, offset) {
+            Ok(x) => x,
+            Err(e) => {
+                println_stderr!("cicada: {}", e);
+                sh.expand_failed = true;
+                return String::new();
+            }
+        };
+        let length = match length {
+            Some(x) if x.trim().is_empty() => Some(0),
+            Some(x) => match eval_arith(sh, x) {
+                Ok(x) => Some(x),
+                Err(e) => {
+                    println_stderr!("cicada: {}", e);
+                    sh.expand_failed = true;
+                    return String::new();
+                }
+            },
+            None => None,
+        };
+        return match substring(&value, offset, length) {
+            Ok(x) => x,
+            Err(e) => {
+                println_stderr!("cicada: {}", e);
+                fail_expansion(sh);
+                String::new()
+            }
+        };
+    }
+
+    match op {
+        "^^" => value.to_uppercase(),
+        ",," => value.to_lowercase(),
+        "^" | "," => {
+            let mut chars = value.chars();
+            match chars.next() {
+                Some(x) if op == "^" => x.to_uppercase().chain(chars).collect(),
+                Some(x) => x.to_lowercase().chain(chars).collect(),
+                None => value,
+            }
+        }
+        _ => bad_substitution(sh, expr),
+    }
+}
+
+/// Index of the first `/` in `text` which is not escaped like `\/`.
+fn find_unescaped_slash(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '/' {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// The array name of `arr[@]` or `arr[*]`.
+fn array_all_items(name: &str) -> Option<&str> {
+    name.strip_suffix("[@]")
+        .or_else(|| name.strip_suffix("[*]"))
+}
+
+/// Remove the shortest (or longest) prefix (or suffix) of `value` which
+/// matches the glob `pattern`.
+fn remove_pattern(value: &str, pattern: &str, is_prefix: bool, longest: bool) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len();
+    let mut sizes: Vec<usize> = (0..=len).collect();
+    if longest {
+        sizes.reverse();
+    }
+    for size in sizes {
+        let (part, rest) = if is_prefix {
+            (&chars[..size], &chars[size..])
+        } else {
+            (&chars[len - size..], &chars[..len - size])
+        };
+        if glob_match(pattern, &part.iter().collect::<String>()) {
+            return rest.iter().collect();
         }
+    }
+    value.to_string()
+}
 
-        if _tail.is_empty() {
+/// Replace the longest matches of the glob `pattern` in `value` with
+/// `word`, `pattern` starting with `#` (or `%`) only matches at the
+/// beginning (or the end) of `value`.
+fn replace_pattern(value: &str, pattern: &str, word: &str, all: bool) -> String {
+    let (pattern, anchor) = if let Some(x) = pattern.strip_prefix('#') {
+        (x, '#')
+    } else if let Some(x) = pattern.strip_prefix('%') {
+        (x, '%')
+    } else {
+        (pattern, ' ')
+    };
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len();
+    let mut result = String::new();
+    let mut i = 0;
+    while i < len {
+        if anchor == '#' && i > 0 {
             break;
         }
-        _token = _tail.clone();
+        let matched = (i + 1..=len).rev().find(|&j| {
+            (anchor != '%' || j == len)
+                && glob_match(pattern, &chars[i..j].iter().collect::<String>())
+        });
+        match matched {
+            Some(j) => {
+                result.push_str(word);
+                i = j;
+                if !all {
+                    break;
+                }
+            }
+            None => {
+                result.push(chars[i]);
+                i += 1;
+            }
+        }
     }
+    result.extend(chars[i..].iter());
     result
 }
 
+/// `${foo:offset:length}`, negative `offset` counts from the end, and
+/// negative `length` means the number of chars to leave at the end.
+fn substring(value: &str, offset: i64, length: Option<i64>) -> Result<String, String> {
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len() as i64;
+    let start = if offset < 0 { len + offset } else { offset };
+    if start < 0 || start > len {
+        return Ok(String::new());
+    }
+    let end = match length {
+        Some(x) if x < 0 => len + x,
+        Some(x) => (start + x).min(len),
+        None => len,
+    };
+    // a negative length counts from the end, which cannot be before start
+    if end < start {
+        return Err(format!("{}: substring expression < 0", length.unwrap_or(0)));
+    }
+    Ok(chars[start as usize..end as usize].iter().collect())
+}
+
 fn expand_brace(tokens: &mut types::Tokens) {
     let mut idx: usize = 0;
     let mut buff: HashMap<usize, Vec<String>> = HashMap::new();
@@ -494,13 +1065,13 @@ fn expand_alias(sh: &Shell, tokens: &mut types::Tokens) {
     let mut buff = Vec::new();
     let mut is_head = true;
     for (sep, text) in tokens.iter() {
-        if sep.is_empty() && text == "|" {
+        if sep.is_empty() && (text == "|" || text == "|&") {
             is_head = true;
             idx += 1;
             continue;
         }
 
-        if !is_head || !sh.is_alias(&text) {
+        if !is_head || sep.ends_with('(') || !sh.is_alias(&text) {
             idx += 1;
             is_head = false;
             continue;
@@ -563,159 +1134,268 @@ fn expand_home(tokens: &mut types::Tokens) {
 }
 
 fn env_in_token(token: &str) -> bool {
-    if token == "$$" || token == "$?" {
-        return true;
-    }
-    tools::re_contains(token, r"\$\{?[a-zA-Z][a-zA-Z0-9_]+\}?")
+    tools::re_contains(token, r"\$(\{|\(\(|[a-zA-Z0-9_\?\$@\*#])")
 }
 
-pub fn expand_env(sh: &Shell, tokens: &mut types::Tokens) {
-    let mut idx: usize = 0;
-    let mut buff: HashMap<usize, String> = HashMap::new();
-
+pub fn expand_env(sh: &mut Shell, tokens: &mut types::Tokens) {
+    let mut result: types::Tokens = Vec::new();
     for (sep, token) in tokens.iter() {
-        if sep == "`" || sep == "'" || !env_in_token(token) {
-            idx += 1;
+        // bodies of here-documents are expanded here all at once, and are
+        // kept from further expansions with `'`
+        let after_heredoc = result.last().is_some_and(|x| x.0.is_empty() && x.1 == "<<");
+        if after_heredoc && sep == "\"" {
+            result.push((String::from("'"), expand_here_document(sh, token)));
+            continue;
+        }
+        if sep == "`" || sep == "'" || sep.ends_with('(') || !env_in_token(token) {
+            result.push((sep.clone(), token.clone()));
             continue;
         }
 
-        let _token = extend_env_blindly(sh, token);
-        buff.insert(idx, _token);
-        idx += 1;
-    }
+        // `"${arr[@]}"` becomes one word for each item of the array, so
+        // does `"${!arr[@]}"` for each key
+        if let Some(name) = token
+            .strip_prefix("${")
+            .and_then(|x| x.strip_suffix("[@]}"))
+        {
+            let (name, is_keys) = match name.strip_prefix('!') {
+                Some(x) => (x, true),
+                None => (name, false),
+            };
+            if let Some(x) = sh.get_array(name) {
+                let words = if is_keys { x.keys() } else { x.values() };
+                for word in words {
+                    result.push((sep.clone(), word));
+                }
+                continue;
+            }
+        }
 
-    for (i, text) in buff.iter() {
-        tokens[*i as usize].1 = text.to_string();
-    }
-}
+        // `"$@"` becomes one word for each positional parameter, so does
+        // `$*` when it is not quoted
+        if token == "$@"
+            || token == "${@}"
+            || (sep.is_empty() && (token == "$*" || token == "${*}"))
+        {
+            for arg in sh.args.iter().skip(1) {
+                result.push((sep.clone(), arg.clone()));
+            }
+            continue;
+        }
 
-fn should_do_dollar_command_extension(line: &str) -> bool {
-    tools::re_contains(line, r"\$\([^\)]+\)")
+        let _token = extend_env(sh, token);
+        result.push((sep.clone(), _token));
+    }
+    *tokens = result;
 }
 
-fn do_command_substitution_for_dollar(sh: &mut Shell, tokens: &mut types::Tokens) {
-    let mut idx: usize = 0;
-    let mut buff: HashMap<usize, String> = HashMap::new();
-
-    for (sep, token) in tokens.iter() {
-        if sep == "'" || sep == "\\" || !should_do_dollar_command_extension(token) {
-            idx += 1;
+/// Expand the body of a here-document whose delimiter is not quoted. Like
+/// in double quotes, backslashes only escape `$`, `` ` ``, `\` and
+/// newlines in it, e.g. `\$HOME` is kept as `$HOME`.
+fn expand_here_document(sh: &mut Shell, body: &str) -> String {
+    let chars: Vec<char> = body.chars().collect();
+    let mut result = String::new();
+    let mut text = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        // backslashes inside command substitutions are left to them
+        let end = if c == '$' && i + 1 < chars.len() && chars[i + 1] == '(' {
+            parsers::parser_line::find_closing_parenthesis(&chars, i + 1)
+        } else if c == '`' {
+            chars[i + 1..]
+                .iter()
+                .position(|&x| x == '`')
+                .map_or(chars.len(), |x| i + 1 + x)
+        } else {
+            i
+        };
+        if end > i {
+            let end = (end + 1).min(chars.len());
+            text.extend(chars[i..end].iter());
+            i = end;
             continue;
         }
 
-        let mut line = token.to_string();
-        loop {
-            if !should_do_dollar_command_extension(&line) {
-                break;
-            }
-            let ptn_cmd = r"\$\(([^\(]+)\)";
-            let cmd;
-            match libs::re::find_first_group(ptn_cmd, &line) {
-                Some(x) => {
-                    cmd = x;
-                }
-                None => {
-                    println_stderr!("cicada: no first group");
-                    return;
-                }
+        if c == '\\' && i + 1 < chars.len() && "$`\\\n".contains(chars[i + 1]) {
+            let expanded = extend_env(sh, &text);
+            result.push_str(&substitute_commands(sh, &expanded, false).concat());
+            text = String::new();
+            if chars[i + 1] != '\n' {
+                result.push(chars[i + 1]);
             }
+            i += 2;
+            continue;
+        }
+        text.push(c);
+        i += 1;
+    }
+    let expanded = extend_env(sh, &text);
+    result.push_str(&substitute_commands(sh, &expanded, false).concat());
+    result
+}
+
+/// Like `extend_env_blindly()`, but leaves command substitutions (i.e.
+/// `$(...)` and backquotes) untouched, they get expanded when being run.
+/// Arithmetic expansions like `$((i + 1))` are done here too.
+fn extend_env(sh: &mut Shell, token: &str) -> String {
+    let mut result = String::new();
+    let mut text = String::new();
+    let chars: Vec<char> = token.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let is_subcmd = c == '`' || (c == '$' && i + 1 < chars.len() && chars[i + 1] == '(');
+        if !is_subcmd {
+            text.push(c);
+            i += 1;
+            continue;
+        }
 
-            log!("run subcmd: {:?}", &cmd);
-            let _args = parsers::parser_line::cmd_to_tokens(&cmd);
-            let (_, cmd_result) =
-                execute::run_pipeline(sh, &_args, "", false, false, true, false, None);
-            let output_txt = cmd_result.stdout.trim();
+        result.push_str(&extend_env_blindly(sh, &text));
+        text = String::new();
 
-            let ptn = r"(?P<head>[^\$]*)\$\([^\(]+\)(?P<tail>.*)";
-            let re;
-            if let Ok(x) = Regex::new(ptn) {
-                re = x;
-            } else {
-                return;
+        let start = i;
+        if c == '`' {
+            i += 1;
+            while i < chars.len() && chars[i] != '`' {
+                i += 1;
             }
-
-            let to = format!("${{head}}{}${{tail}}", output_txt);
-            let line_ = line.clone();
-            let result = re.replace(&line_, to.as_str());
-            line = result.to_string();
+        } else {
+            i = parsers::parser_line::find_closing_parenthesis(&chars, i + 1);
         }
+        i += 1;
+        let end = if i > chars.len() { chars.len() } else { i };
+        let subcmd: String = chars[start..end].iter().collect();
+        if subcmd.starts_with("$((") && subcmd.ends_with("))") {
+            result.push_str(&expand_arith(sh, &subcmd[3..subcmd.len() - 2]));
+        } else {
+            result.push_str(&subcmd);
+        }
+    }
+    result.push_str(&extend_env_blindly(sh, &text));
+    result
+}
 
-        buff.insert(idx, line.clone());
-        idx += 1;
+/// Expand arithmetic expansions like `$((i + 1))`, `expr` is the text
+/// inside the double parentheses.
+fn expand_arith(sh: &mut Shell, expr: &str) -> String {
+    match eval_arith(sh, expr) {
+        Ok(x) => x.to_string(),
+        Err(e) => {
+            println_stderr!("cicada: {}", e);
+            sh.expand_failed = true;
+            String::new()
+        }
     }
+}
 
-    for (i, text) in buff.iter() {
-        tokens[*i as usize].1 = text.to_string();
+/// Evaluate arithmetic expressions like `$n * 2 + i`, parameters and
+/// command substitutions in them get expanded before the evaluation.
+pub fn eval_arith(sh: &mut Shell, expr: &str) -> Result<i64, String> {
+    let mut tokens = vec![(String::from("\""), extend_env(sh, expr))];
+    do_command_substitution(sh, &mut tokens, false);
+    arith::eval(sh, &tokens[0].1)
+}
+
+/// Whether `line` has command substitutions like `$(date)`.
+fn should_do_dollar_command_extension(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().collect();
+    let mut i = 0;
+    while i + 1 < chars.len() {
+        if chars[i] == '\\' {
+            i += 2;
+            continue;
+        }
+        if chars[i] == '$'
+            && chars[i + 1] == '('
+            && parsers::parser_script::scan_command_substitution(&chars, i).is_ok()
+        {
+            return true;
+        }
+        i += 1;
     }
+    false
 }
 
-fn do_command_substitution_for_dot(sh: &mut Shell, tokens: &mut types::Tokens) {
-    let mut idx: usize = 0;
-    let mut buff: HashMap<usize, String> = HashMap::new();
+/// Replace command substitutions like `$(date)` and `` `date` `` with the
+/// outputs of the commands. When `split` is true, outputs in unquoted
+/// words are split into more words by whitespaces.
+fn do_command_substitution(sh: &mut Shell, tokens: &mut types::Tokens, split: bool) {
+    let mut result = Vec::new();
     for (sep, token) in tokens.iter() {
-        let new_token: String;
         if sep == "`" {
-            log!("run subcmd: {:?}", token);
-            let _args = parsers::parser_line::cmd_to_tokens(&token);
-            let (_, cr) = execute::run_pipeline(sh, &_args, "", false, false, true, false, None);
-            new_token = cr.stdout.trim().to_string();
-        } else if sep == "\"" || sep.is_empty() {
-            let re;
-            if let Ok(x) = Regex::new(r"^([^`]*)`([^`]+)`(.*)$") {
-                re = x;
-            } else {
-                println_stderr!("cicada: re new error");
-                return;
-            }
-            if !re.is_match(&token) {
-                idx += 1;
-                continue;
-            }
-            let mut _token = token.clone();
-            let mut _item = String::new();
-            let mut _head = String::new();
-            let mut _output = String::new();
-            let mut _tail = String::new();
-            loop {
-                if !re.is_match(&_token) {
-                    if !_token.is_empty() {
-                        _item = format!("{}{}", _item, _token);
-                    }
-                    break;
+            let output = execute::run_command_substitution(sh, token);
+            if split {
+                for word in output.split_whitespace() {
+                    result.push((sep.clone(), word.to_string()));
                 }
-                for cap in re.captures_iter(&_token) {
-                    _head = cap[1].to_string();
-                    _tail = cap[3].to_string();
-                    log!("run subcmd: {:?}", &cap[2]);
-                    let _args = parsers::parser_line::cmd_to_tokens(&cap[2]);
-                    let (_, cr) =
-                        execute::run_pipeline(sh, &_args, "", false, false, true, false, None);
-                    _output = cr.stdout.trim().to_string();
-                }
-                _item = format!("{}{}{}", _item, _head, _output);
-                if _tail.is_empty() {
-                    break;
-                }
-                _token = _tail.clone();
+            } else {
+                result.push((sep.clone(), output));
             }
-            new_token = _item;
-        } else {
-            idx += 1;
             continue;
         }
 
-        buff.insert(idx, new_token.clone());
-        idx += 1;
-    }
-
-    for (i, text) in buff.iter() {
-        tokens[*i as usize].1 = text.to_string();
+        let is_subcmd = should_do_dollar_command_extension(token) || token.contains('`');
+        if !is_subcmd || (sep != "\"" && !sep.is_empty()) {
+            result.push((sep.clone(), token.clone()));
+            continue;
+        }
+        // no splitting for assignments like `foo=$(date)`
+        let split = split && sep.is_empty() && !is_assignment(token);
+        for word in substitute_commands(sh, token, split) {
+            result.push((sep.clone(), word));
+        }
     }
+    *tokens = result;
 }
 
-fn do_command_substitution(sh: &mut Shell, tokens: &mut types::Tokens) {
-    do_command_substitution_for_dot(sh, tokens);
-    do_command_substitution_for_dollar(sh, tokens);
+/// Run the command substitutions in `token`, returns the words of it,
+/// which is always one word unless `split` is true.
+fn substitute_commands(sh: &mut Shell, token: &str, split: bool) -> Vec<String> {
+    let chars: Vec<char> = token.chars().collect();
+    let mut words = vec![String::new()];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let word = words.last_mut().unwrap();
+        if c == '\\' {
+            word.extend(chars[i..(i + 2).min(chars.len())].iter());
+            i += 2;
+            continue;
+        }
+
+        // the command is in `chars[start..end]`
+        let (start, end) = if c == '$' && i + 1 < chars.len() && chars[i + 1] == '(' {
+            let end = parsers::parser_line::find_closing_parenthesis(&chars, i + 1);
+            (i + 2, end)
+        } else if c == '`' && chars[i + 1..].contains(&'`') {
+            let len = chars[i + 1..].iter().position(|&x| x == '`').unwrap_or(0);
+            (i + 1, i + 1 + len)
+        } else {
+            word.push(c);
+            i += 1;
+            continue;
+        };
+        let cmd: String = chars[start..end.min(chars.len())].iter().collect();
+        let output = execute::run_command_substitution(sh, &cmd);
+        i = end + 1;
+
+        if !split {
+            word.push_str(&output);
+            continue;
+        }
+        for (n, field) in output.split(char::is_whitespace).enumerate() {
+            if n > 0 && !words[words.len() - 1].is_empty() {
+                words.push(String::new());
+            }
+            words.last_mut().unwrap().push_str(field);
+        }
+    }
+    if split {
+        words.retain(|x| !x.is_empty());
+    }
+    words
 }
 
 pub fn do_expansion(sh: &mut Shell, tokens: &mut types::Tokens) {
@@ -726,11 +1406,155 @@ pub fn do_expansion(sh: &mut Shell, tokens: &mut types::Tokens) {
     }
 
     expand_alias(sh, tokens);
+    do_word_expansion(sh, tokens);
+}
+
+/// Expansions other than alias, e.g. for the word list of `for` loops.
+pub fn do_word_expansion(sh: &mut Shell, tokens: &mut types::Tokens) {
     expand_home(tokens);
     expand_brace(tokens);
     expand_env(sh, tokens);
+    if sh.expand_failed {
+        return;
+    }
     expand_glob(tokens);
-    do_command_substitution(sh, tokens);
+    do_command_substitution(sh, tokens, true);
+    do_process_substitution(sh, tokens);
+}
+
+/// Replace process substitutions like `<(sort a.txt)` with paths like
+/// `/dev/fd/63`, which are pipes to the commands in child processes.
+fn do_process_substitution(sh: &mut Shell, tokens: &mut types::Tokens) {
+    for token in tokens.iter_mut() {
+        if token.0 != "<(" && token.0 != ">(" {
+            continue;
+        }
+        let is_input = token.0 == "<(";
+        match execute::run_proc_substitution(sh, &token.1, is_input) {
+            Ok(fd) => *token = (String::new(), format!("/dev/fd/{}", fd)),
+            Err(e) => {
+                println_stderr!("cicada: {}", e);
+                *token = (String::new(), String::new());
+            }
+        }
+    }
+}
+
+/// Expansions for a single word, without splitting it or globbing,
+/// e.g. the word and patterns of `case`.
+pub fn expand_word(sh: &mut Shell, tokens: &mut types::Tokens) {
+    expand_home(tokens);
+    expand_env(sh, tokens);
+    do_command_substitution(sh, tokens, false);
+}
+
+/// Expand a list of words like the ones of `for` loops and arrays, e.g.
+/// `a "b c" $d`, unquoted words are split by whitespaces.
+pub fn expand_word_list(sh: &mut Shell, text: &str) -> Vec<String> {
+    let mut tokens = parsers::parser_line::cmd_to_tokens(text);
+    do_word_expansion(sh, &mut tokens);
+    let mut result = Vec::new();
+    for (sep, token) in tokens {
+        if sep.is_empty() {
+            result.extend(token.split_whitespace().map(|x| x.to_string()));
+        } else {
+            result.push(token);
+        }
+    }
+    result
+}
+
+/// Is `text` an assignment like `foo=bar`, `foo+=bar`, `arr=(a b)` or
+/// `arr[1]=c`?
+pub fn is_assignment(text: &str) -> bool {
+    tools::re_contains(text, r"^[a-zA-Z_][a-zA-Z0-9_]*(\[[^\]]*\])?\+?=")
+}
+
+/// Is `text` an assignment which `run_assignment()` takes care of, rather
+/// than a plain one like `foo=bar`?
+pub fn is_array_assignment(text: &str) -> bool {
+    tools::re_contains(text, r"^[a-zA-Z_][a-zA-Z0-9_]*(\[[^\]]*\]\+?=|\+=|=\()")
+}
+
+/// Run assignments like `foo=bar`, `foo+=bar`, `arr=(a b c)`, `arr+=(d)`,
+/// `map=([k1]=a [k2]=b)` and `arr[1]=x`.
+pub fn run_assignment(sh: &mut Shell, text: &str) -> Result<(), String> {
+    let re = match Regex::new(r"(?s)^([a-zA-Z_][a-zA-Z0-9_]*)(\[([^\]]*)\])?(\+?)=(.*)$") {
+        Ok(x) => x,
+        Err(e) => return Err(format!("Regex error: {:?}", e)),
+    };
+    let cap = match re.captures(text) {
+        Some(x) => x,
+        None => return Err(format!("`{}': not a valid identifier", text)),
+    };
+    let name = &cap[1];
+    let append = &cap[4] == "+";
+    let value = &cap[5];
+
+    if cap.get(2).is_none() && value.starts_with('(') && value.ends_with(')') {
+        let mut array = match sh.get_array(name) {
+            Some(x) if append => x.clone(),
+            Some(types::Array::Assoc(_)) => types::Array::Assoc(BTreeMap::new()),
+            _ => {
+                let mut array = types::Array::Indexed(BTreeMap::new());
+                if append {
+                    if let Some(x) = get_param(sh, name) {
+                        array.push(&x);
+                    }
+                }
+                array
+            }
+        };
+        let is_assoc = matches!(array, types::Array::Assoc(_));
+        let re = match Regex::new(r"(?s)^\[([^\]]*)\]=(.*)$") {
+            Ok(x) => x,
+            Err(e) => return Err(format!("Regex error: {:?}", e)),
+        };
+        for word in expand_word_list(sh, &value[1..value.len() - 1]) {
+            // items with keys like `[k1]=a`
+            if let Some(cap) = re.captures(&word) {
+                let key = item_key(sh, name, &cap[1], is_assoc, array.next_index())?;
+                array.set(&key, &cap[2]);
+            } else if is_assoc {
+                return Err(format!("{}: {}: must use subscript", name, word));
+            } else {
+                array.push(&word);
+            }
+        }
+        sh.set_array(name, array);
+        return Ok(());
+    }
+
+    let mut tokens = parsers::parser_line::cmd_to_tokens(value);
+    expand_word(sh, &mut tokens);
+    let words: Vec<String> = tokens.into_iter().map(|x| x.1).collect();
+    let mut value = words.join(" ");
+    match cap.get(3) {
+        Some(sub) => {
+            let key = array_key(sh, name, sub.as_str())?;
+            if append {
+                let old = sh.get_array(name).and_then(|x| x.get(&key).cloned());
+                value = format!("{}{}", old.unwrap_or_default(), value);
+            }
+            if sh.get_array(name).is_none() {
+                let mut array = types::Array::Indexed(BTreeMap::new());
+                if let Some(x) = sh.get_env(name) {
+                    array.push(&x);
+                }
+                sh.set_array(name, array);
+            }
+            if let Some(x) = sh.get_array_mut(name) {
+                x.set(&key, &value);
+            }
+        }
+        None => {
+            if append {
+                value = format!("{}{}", get_param(sh, name).unwrap_or_default(), value);
+            }
+            sh.set_env(name, &value);
+        }
+    }
+    Ok(())
 }
 
 pub fn needs_expand_home(line: &str) -> bool {
@@ -740,10 +1564,14 @@ pub fn needs_expand_home(line: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::expand_alias;
+    use super::extend_env;
+    use super::extend_env_blindly;
+    use super::glob_match;
     use super::needs_expand_home;
     use super::needs_globbing;
     use super::should_do_dollar_command_extension;
     use super::Shell;
+    use crate::scripting::{check, run_lines};
 
     #[test]
     fn test_need_expand_home() {
@@ -813,4 +1641,134 @@ mod tests {
         expand_alias(&sh, &mut tokens);
         assert_eq!(tokens, exp_tokens);
     }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "foo"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(glob_match("*.rs", "src/main.rs"));
+        assert!(glob_match("f?o", "foo"));
+        assert!(glob_match("[Dd]arwin", "Darwin"));
+        assert!(glob_match("[a-c]*", "cicada"));
+        assert!(!glob_match("*.rs", "main.py"));
+        assert!(!glob_match("f?o", "fooo"));
+        assert!(!glob_match("Linux", "linux"));
+    }
+
+    #[test]
+    fn test_extend_env_blindly() {
+        let mut sh = Shell::new();
+        sh.set_env("CICADA_TEST_A", "foo");
+        sh.set_env("CICADA_TEST_B", "bar");
+        let v = vec![
+            ("$CICADA_TEST_A", "foo"),
+            ("$CICADA_TEST_A$CICADA_TEST_B", "foobar"),
+            ("x${CICADA_TEST_A}-$CICADA_TEST_B.", "xfoo-bar."),
+            ("$CICADA_TEST_NOT_DEFINED", ""),
+            ("no env", "no env"),
+        ];
+        for (left, right) in v {
+            assert_eq!(extend_env_blindly(&mut sh, left), right);
+        }
+    }
+
+    #[test]
+    fn test_expand_params() {
+        let mut sh = Shell::new();
+        sh.set_env("CICADA_TEST_P", "/usr/lib/foo.tar.gz");
+        sh.set_env("CICADA_TEST_E", "");
+        let v = vec![
+            ("${CICADA_TEST_NOT_DEFINED:-a b}", "a b"),
+            ("${CICADA_TEST_E:-empty}", "empty"),
+            ("${CICADA_TEST_E-unset}", ""),
+            ("${CICADA_TEST_E:+alt}${CICADA_TEST_P:+alt}", "alt"),
+            ("${CICADA_TEST_N:-${CICADA_TEST_E:-x}}", "x"),
+            ("${#CICADA_TEST_P}", "19"),
+            ("${CICADA_TEST_P#*/}", "usr/lib/foo.tar.gz"),
+            ("${CICADA_TEST_P##*/}", "foo.tar.gz"),
+            ("${CICADA_TEST_P%.*}", "/usr/lib/foo.tar"),
+            ("${CICADA_TEST_P%%.*}", "/usr/lib/foo"),
+            ("${CICADA_TEST_P/o/0}", "/usr/lib/f0o.tar.gz"),
+            ("${CICADA_TEST_P//o/0}", "/usr/lib/f00.tar.gz"),
+            ("${CICADA_TEST_P/#\\/usr/~}", "~/lib/foo.tar.gz"),
+            ("${CICADA_TEST_P/%gz/bz2}", "/usr/lib/foo.tar.bz2"),
+            ("${CICADA_TEST_P:9}", "foo.tar.gz"),
+            ("${CICADA_TEST_P:9:3}", "foo"),
+            ("${CICADA_TEST_P: -2}", "gz"),
+            ("${CICADA_TEST_P:1:-7}", "usr/lib/foo"),
+            ("${CICADA_TEST_P:4+5:1+2}", "foo"),
+            ("${CICADA_TEST_P: -99:2}${CICADA_TEST_P:1:}", ""),
+            ("${CICADA_TEST_P^^}", "/USR/LIB/FOO.TAR.GZ"),
+        ];
+        for (left, right) in v {
+            assert_eq!(extend_env_blindly(&mut sh, left), right);
+        }
+        assert!(!sh.expand_failed);
+
+        assert_eq!(extend_env_blindly(&mut sh, "${CICADA_TEST_D:=foo}"), "foo");
+        assert_eq!(sh.get_env("CICADA_TEST_D"), Some(String::from("foo")));
+
+        for text in vec!["${CICADA_TEST_E:?}", "${CICADA_TEST_P:1:-99}", "${!}"] {
+            sh.expand_failed = false;
+            assert_eq!(extend_env_blindly(&mut sh, text), "");
+            assert!(sh.expand_failed);
+        }
+    }
+
+    #[test]
+    fn test_expand_arith() {
+        let mut sh = Shell::new();
+        sh.set_env("CICADA_TEST_N", "5");
+        let v = vec![
+            ("$((1 + 2))", "3"),
+            ("a$(( (1 + 2) * 3 ))b", "a9b"),
+            ("$((CICADA_TEST_N * 2)) $(( $CICADA_TEST_N % 3 ))", "10 2"),
+            ("$(( $((1 + 1)) ** 3 ))", "8"),
+            ("$((CICADA_TEST_I = 7)).$((CICADA_TEST_I++))", "7.7"),
+            ("$(echo 1)", "$(echo 1)"),
+        ];
+        for (left, right) in v {
+            assert_eq!(extend_env(&mut sh, left), right);
+        }
+        assert_eq!(sh.get_env("CICADA_TEST_I"), Some(String::from("8")));
+        assert!(!sh.expand_failed);
+
+        assert_eq!(extend_env(&mut sh, "$((1 / 0))"), "");
+        assert!(sh.expand_failed);
+    }
+
+    #[test]
+    fn test_run_arrays() {
+        let mut sh = Shell::new();
+        let v = [
+            (
+                "a=(x 'y z' w); S=\"${a[1]}|${a[-1]}|$a|${#a[@]}\"",
+                "y z|w|x|3",
+            ),
+            (
+                "a=(x 'y z'); for i in \"${a[@]}\"; do S=$S-$i; done",
+                "-x-y z",
+            ),
+            (
+                "a=(); a+=(x y); a[5]=z; S=\"${!a[@]}: ${a[@]}\"",
+                "0 1 5: x y z",
+            ),
+            ("a=(x y); i=0; a[i+1]+=z; S=${a[1]}", "yz"),
+            ("S=a; S+=b; S+=\"c d\"", "abc d"),
+            (
+                "declare -A m; m[k1]=a; k=k2; m[$k]='b c'; S=\"${#m[@]} ${m[k2]}\"",
+                "2 b c",
+            ),
+            (
+                "declare -A m=([x]=1 [y]=2); for k in \"${!m[@]}\"; do S=$S$k=${m[$k]},; done",
+                "x=1,y=2,",
+            ),
+        ];
+        check(&mut sh, &v);
+
+        assert_eq!(run_lines(&mut sh, "declare -A m=(x)"), 1);
+        assert_eq!(run_lines(&mut sh, "a=(x); declare -A a"), 1);
+        assert_eq!(run_lines(&mut sh, "a=(x); a[-5]=y"), 1);
+    }
 }
@@ -0,0 +1,88 @@
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Signals caught by `trap` but not handled yet, one bit for each.
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+/// Names (without the `SIG` prefix) and numbers of the signals known to
+/// `trap`.
+pub const SIGNALS: [(&str, i32); 29] = [
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("IO", libc::SIGIO),
+    ("SYS", libc::SIGSYS),
+];
+
+/// The number of a signal given like `INT`, `SIGINT`, `int` or `2`.
+pub fn number(name: &str) -> Option<i32> {
+    if let Ok(x) = name.parse::<i32>() {
+        return SIGNALS.iter().find(|s| s.1 == x).map(|s| s.1);
+    }
+    let name = name.to_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS.iter().find(|s| s.0 == name).map(|s| s.1)
+}
+
+/// The name of signal `num` without the `SIG` prefix, e.g. `INT`.
+pub fn name(num: i32) -> Option<&'static str> {
+    SIGNALS.iter().find(|s| s.1 == num).map(|s| s.0)
+}
+
+extern "C" fn handle_signal(sig: libc::c_int) {
+    PENDING.fetch_or(1 << sig, Ordering::SeqCst);
+}
+
+/// Catch signal `sig`, it would be returned by `take_pending()`.
+pub fn catch(sig: i32) -> io::Result<()> {
+    set_handler(sig, handle_signal as *const () as libc::sighandler_t)
+}
+
+pub fn ignore(sig: i32) -> io::Result<()> {
+    set_handler(sig, libc::SIG_IGN)
+}
+
+pub fn reset(sig: i32) -> io::Result<()> {
+    set_handler(sig, libc::SIG_DFL)
+}
+
+fn set_handler(sig: i32, handler: libc::sighandler_t) -> io::Result<()> {
+    if unsafe { libc::signal(sig, handler) } == libc::SIG_ERR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Signals caught since last call, in the order of their numbers.
+pub fn take_pending() -> Vec<i32> {
+    let pending = PENDING.swap(0, Ordering::SeqCst);
+    SIGNALS
+        .iter()
+        .map(|s| s.1)
+        .filter(|x| pending & (1 << x) != 0)
+        .collect()
+}
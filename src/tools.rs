@@ -1,10 +1,10 @@
 use std::env;
-use std::error::Error;
-use std::fs::File;
+use std::fs;
 use std::fs::OpenOptions;
-use std::io::Write;
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::io::IntoRawFd;
-use std::path::Path;
+use std::process;
 
 use libc;
 use regex::Regex;
@@ -113,18 +113,6 @@ pub fn get_rc_file() -> String {
     format!("{}/{}", home, ".cicadarc")
 }
 
-pub fn unquote(s: &str) -> String {
-    let args = parsers::parser_line::line_to_plain_tokens(s);
-    if args.is_empty() {
-        return String::new();
-    }
-    args[0].clone()
-}
-
-pub fn is_export_env(line: &str) -> bool {
-    re_contains(line, r"^ *export +[a-zA-Z0-9_]+=.*$")
-}
-
 pub fn is_env(line: &str) -> bool {
     re_contains(line, r"^[a-zA-Z0-9_]+=.*$")
 }
@@ -211,25 +199,6 @@ pub fn wrap_sep_string(sep: &str, s: &str) -> String {
     format!("{}{}{}", sep, _token, sep)
 }
 
-pub fn env_args_to_command_line() -> String {
-    let mut result = String::new();
-    let env_args = env::args();
-    if env_args.len() <= 1 {
-        return result;
-    }
-    for (i, arg) in env_args.enumerate() {
-        if i == 0 || arg == "-c" {
-            continue;
-        }
-        result.push_str(arg.as_str());
-    }
-    result
-}
-
-pub fn is_alias(line: &str) -> bool {
-    re_contains(line, r"^ *alias +[a-zA-Z0-9_\.-]+=.*$")
-}
-
 extern "C" {
     fn gethostname(name: *mut libc::c_char, size: libc::size_t) -> libc::c_int;
 }
@@ -263,11 +232,40 @@ pub fn get_hostname() -> String {
     }
 }
 
+/// Whether the line is for the calculator, e.g. `(1 + 2) * 3`,
+/// `sqrt(ans) / 2` or `0xff in bin`.
 pub fn is_arithmetic(line: &str) -> bool {
-    if !re_contains(line, r"[0-9]+") {
+    if !re_contains(line, r"[0-9]|\bans\b") {
         return false;
     }
-    re_contains(line, r"^[ 0-9\.\(\)\+\-\*/]+$")
+    // `((1 + 2))` is the arithmetic command, but not `((1 + 2) * 3)`
+    let line = line.trim();
+    if line.starts_with("((") && line.ends_with("))") {
+        let inner = &line[2..line.len() - 2];
+        let mut depth = 0;
+        for c in inner.chars() {
+            if c == '(' {
+                depth += 1;
+            } else if c == ')' {
+                depth -= 1;
+                if depth < 0 {
+                    break;
+                }
+            }
+        }
+        if depth == 0 {
+            return false;
+        }
+    }
+    // `1 > 2` or `1 >> 2` would be redirections, and `name (1)` a command
+    if line.contains('>') {
+        return false;
+    }
+    let token = r"0[xX][0-9a-fA-F]+|0[oO][0-7]+|0[bB][01]+|[0-9]*\.?[0-9]+\.?|ans|[a-z]+[0-9]*\(|<<|[-+*/%()&|^~,\s]";
+    re_contains(
+        line,
+        &format!(r"^({})+(\s+in\s+(hex|oct|bin|dec))?$", token),
+    )
 }
 
 pub fn re_contains(text: &str, ptn: &str) -> bool {
@@ -284,34 +282,54 @@ pub fn re_contains(text: &str, ptn: &str) -> bool {
     re.is_match(text)
 }
 
-pub fn create_raw_fd_from_file(file_name: &str, append: bool) -> Result<i32, String> {
+/// Open the file of redirections like `> foo.log`, `>> foo.log`,
+/// `< foo.txt` or `<> foo.txt`.
+pub fn create_raw_fd_from_file(file_name: &str, op: &str) -> Result<i32, String> {
     let mut oos = OpenOptions::new();
-    if append {
-        oos.append(true);
-    } else {
-        oos.write(true);
-        oos.truncate(true);
-    }
-    match oos.create(true).open(file_name) {
+    match op {
+        ">" | ">|" => oos.write(true).create(true).truncate(true),
+        ">>" => oos.append(true).create(true),
+        "<" => oos.read(true),
+        _ => oos.read(true).write(true).create(true),
+    };
+    match oos.open(file_name) {
         Ok(x) => {
             let fd = x.into_raw_fd();
             Ok(fd)
         }
-        Err(e) => Err(format!("failed to create fd from file: {:?}", e)),
+        Err(e) => Err(format!("{}: {}", file_name, e)),
     }
 }
 
-pub fn get_fd_from_file(file_name: &str) -> i32 {
-    let path = Path::new(file_name);
-    let display = path.display();
-    let file = match File::open(&path) {
-        Err(why) => {
-            println_stderr!("cicada: could not open {}: {}", display, why.description());
-            return 0;
+/// Create a fd to read `text` from, e.g. for here-documents. The text
+/// is kept in an unlinked temp file, so that it could be of any size.
+/// The file must be a new one, never something (or a symlink) someone
+/// else put at the path before us.
+pub fn create_raw_fd_from_text(text: &str) -> Result<i32, String> {
+    let mut n = 0;
+    let (mut file, path) = loop {
+        let path = env::temp_dir().join(format!("cicada-heredoc-{}-{}", process::id(), n));
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&path)
+        {
+            Ok(x) => break (x, path),
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists && n < 100 => n += 1,
+            Err(e) => return Err(format!("failed to create temp file: {:?}", e)),
         }
-        Ok(file) => file,
     };
-    file.into_raw_fd()
+    fs::remove_file(&path).ok();
+    if let Err(e) = file.write_all(text.as_bytes()) {
+        return Err(format!("failed to write temp file: {:?}", e));
+    }
+    if let Err(e) = file.seek(SeekFrom::Start(0)) {
+        return Err(format!("failed to seek temp file: {:?}", e));
+    }
+    Ok(file.into_raw_fd())
 }
 
 pub fn escape_path(path: &str) -> String {
@@ -329,14 +347,43 @@ pub fn escape_path(path: &str) -> String {
 
 #[cfg(test)]
 mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Read;
+    use std::os::unix::fs::symlink;
+    use std::os::unix::io::FromRawFd;
+    use std::process;
+
+    use super::create_raw_fd_from_text;
     use super::escape_path;
     use super::extend_bandband;
-    use super::is_alias;
+    use super::is_arithmetic;
     use crate::shell;
 
     #[test]
-    fn test_is_alias() {
-        assert!(is_alias("alias ls='ls -lh'"));
+    fn test_is_arithmetic() {
+        for line in vec![
+            "1 + 2",
+            "(1 + 2) * 3",
+            "0xff in bin",
+            "sqrt(ans) / 2",
+            "~0 & 3",
+            "1 << 4",
+        ] {
+            assert!(is_arithmetic(line));
+        }
+        for line in vec![
+            "((i = 1 + 2))",
+            "ls -1",
+            "echo 1 + 2",
+            "1 in octal",
+            "1 > 2",
+            "8 >> 1",
+            "1 < 2",
+            "name (1)",
+        ] {
+            assert!(!is_arithmetic(line));
+        }
     }
 
     #[test]
@@ -368,4 +415,22 @@ mod tests {
             "a\\ b\\!c\\\"d\\\'\\#\\$\\&e\\(f\\)g\\*h\\,i\\;j\\<k\\>l\\?m\\\\n\\[\\]o\\`p\\{\\}q\\|\\^z.txt",
         );
     }
+
+    #[test]
+    fn test_create_raw_fd_from_text() {
+        // a symlink planted at the temp path must not be followed
+        let dir = env::temp_dir();
+        let target = dir.join(format!("cicada-heredoc-target-{}", process::id()));
+        let link = dir.join(format!("cicada-heredoc-{}-0", process::id()));
+        symlink(&target, &link).unwrap();
+
+        let fd = create_raw_fd_from_text("foo\n").unwrap();
+        let mut text = String::new();
+        unsafe { File::from_raw_fd(fd) }
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "foo\n");
+        assert!(!target.exists());
+        fs::remove_file(&link).unwrap();
+    }
 }
@@ -1,6 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 pub const STOPPED: i32 = 148;
+// status of commands killed by SIGINT (Ctrl-C)
+pub const INTERRUPTED: i32 = 130;
 
 pub type Tokens = Vec<(String, String)>;
 pub type Redirection = (String, String, String);
@@ -15,12 +17,138 @@ pub type Redirection = (String, String, String);
 ///     ],
 /// }
 ///
+/// here-documents like `cat <<EOF` are redirections of fd 0:
+/// `("0", "<<", "body of it\n")`, so are here-strings like `cat <<< foo`:
+/// `("0", "<<<", "foo\n")`
+///
 #[derive(Debug)]
 pub struct Command {
     pub tokens: Tokens,
     pub redirects: Vec<Redirection>,
 }
 
+/// A command in a script, and how it joins the previous one:
+/// `;` (or newline), `&&` or `||`. With `negate`, the command is
+/// prefixed with `!`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub sep: String,
+    pub negate: bool,
+    pub node: Node,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// A simple command or a pipeline, e.g. `ls -lh | wc -l`, kept as
+    /// text so that it runs through `execute::run_proc()`.
+    Cmd(String),
+    /// `if A; then B; elif C; then D; else E; fi` would be:
+    /// `If(vec![(A, B), (C, D)], E)`
+    If(Vec<(Vec<Stmt>, Vec<Stmt>)>, Vec<Stmt>),
+    /// `for x in a b c; do ...; done` would be:
+    /// `For("x", Some("a b c"), body)`. Without `in`, it loops over
+    /// the positional parameters.
+    For(String, Option<String>, Vec<Stmt>),
+    /// `for ((i = 0; i < 3; i++)); do ...; done` would be:
+    /// `ForArith("i = 0", "i < 3", "i++", body)`
+    ForArith(String, String, String, Vec<Stmt>),
+    /// `(( i++ ))` would be: `Arith(" i++ ")`
+    Arith(String),
+    /// `[[ -f $x && $y == a* ]]` would be:
+    /// `Cond(["-f", "$x", "&&", "$y", "==", "a*"])`
+    Cond(Vec<String>),
+    /// `while A; do B; done`
+    While(Vec<Stmt>, Vec<Stmt>),
+    /// `until A; do B; done`
+    Until(Vec<Stmt>, Vec<Stmt>),
+    /// `case $x in a|b) foo;; *) bar;& esac` would be:
+    /// `Case("$x", [(["a", "b"], foo, ";;"), (["*"], bar, ";&")])`
+    Case(String, Vec<(Vec<String>, Vec<Stmt>, String)>),
+    /// `foo() { echo foo; }` would be: `FuncDef("foo", [echo foo])`
+    FuncDef(String, Vec<Stmt>),
+    /// `{ foo; bar; }`
+    Group(Vec<Stmt>),
+    /// compound commands with redirections, e.g. `{ foo; bar; } > foo.log`
+    /// would be: `Redirect(Group([foo, bar]), "> foo.log")`
+    Redirect(Box<Node>, String),
+}
+
+/// Values of array variables: `arr=(a b c)` makes an indexed one, which
+/// could be sparse like after `arr[10]=x`, and `declare -A map` makes an
+/// associative one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Array {
+    Indexed(BTreeMap<usize, String>),
+    Assoc(BTreeMap<String, String>),
+}
+
+impl Array {
+    pub fn get(&self, key: &str) -> Option<&String> {
+        match self {
+            Array::Indexed(x) => key.parse().ok().and_then(|i: usize| x.get(&i)),
+            Array::Assoc(x) => x.get(key),
+        }
+    }
+
+    /// Set the item, `key` of indexed arrays should be a number.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self {
+            Array::Indexed(x) => {
+                if let Ok(i) = key.parse() {
+                    x.insert(i, value.to_string());
+                }
+            }
+            Array::Assoc(x) => {
+                x.insert(key.to_string(), value.to_string());
+            }
+        }
+    }
+
+    /// Append to an indexed array, after its last item.
+    pub fn push(&mut self, value: &str) {
+        let i = self.next_index();
+        if let Array::Indexed(x) = self {
+            x.insert(i, value.to_string());
+        }
+    }
+
+    /// The index after the last item of indexed arrays.
+    pub fn next_index(&self) -> usize {
+        match self {
+            Array::Indexed(x) => x.keys().next_back().map_or(0, |i| i + 1),
+            Array::Assoc(_) => 0,
+        }
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        match self {
+            Array::Indexed(x) => x.keys().map(|i| i.to_string()).collect(),
+            Array::Assoc(x) => x.keys().cloned().collect(),
+        }
+    }
+
+    pub fn values(&self) -> Vec<String> {
+        match self {
+            Array::Indexed(x) => x.values().cloned().collect(),
+            Array::Assoc(x) => x.values().cloned().collect(),
+        }
+    }
+}
+
+/// Variables declared with `local` in a function call, a name is either
+/// in `envs` or in `arrays`, e.g. `local x arr=(a b)`.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    pub envs: HashMap<String, String>,
+    pub arrays: HashMap<String, Array>,
+}
+
+impl Scope {
+    pub fn contains(&self, name: &str) -> bool {
+        self.envs.contains_key(name) || self.arrays.contains_key(name)
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Job {
     pub cmd: String,
@@ -70,9 +198,32 @@ impl CommandResult {
 
 #[derive(Clone, Debug, Default)]
 pub struct CommandOptions {
-    pub redirect_from: String,
     pub background: bool,
     pub isatty: bool,
     pub capture_output: bool,
     pub envs: HashMap<String, String>,
 }
+
+/// Shell options changed with `set`, e.g. `set -e` and `set -o pipefail`.
+#[derive(Clone, Debug, Default)]
+pub struct ShellOptions {
+    pub errexit: bool,
+    pub nounset: bool,
+    pub xtrace: bool,
+    pub pipefail: bool,
+    pub noclobber: bool,
+}
+
+impl ShellOptions {
+    /// The option with long name `name`, e.g. `"errexit"`.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "errexit" => Some(&mut self.errexit),
+            "nounset" => Some(&mut self.nounset),
+            "xtrace" => Some(&mut self.xtrace),
+            "pipefail" => Some(&mut self.pipefail),
+            "noclobber" => Some(&mut self.noclobber),
+            _ => None,
+        }
+    }
+}
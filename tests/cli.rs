@@ -0,0 +1,17 @@
+use std::process::Command;
+
+fn run_c(line: &str) -> i32 {
+    let output = Command::new(env!("CARGO_BIN_EXE_cicada"))
+        .args(["-c", line])
+        .output()
+        .expect("failed to run cicada");
+    output.status.code().unwrap_or(-1)
+}
+
+#[test]
+fn test_dash_c_status() {
+    assert_eq!(run_c("true"), 0);
+    assert_eq!(run_c("false"), 1);
+    assert_eq!(run_c("exit 3"), 3);
+    assert_eq!(run_c("trap 'echo bye' EXIT; sh -c 'exit 4'"), 4);
+}